@@ -0,0 +1,515 @@
+use crate::keymap::KEYMAP_PATH;
+use crate::mapgen::Generator;
+use crate::templates::color_from;
+use crate::toml::{DataError, Document, Entry, Table, TableReader, Value};
+use std::path::Path;
+use tcod::colors::Color;
+use tcod::map::FovAlgorithm;
+
+/// where the configuration is read from unless `--config` says otherwise
+pub const CONFIG_PATH: &str = "config.toml";
+
+/// Everything about the window, the dungeon generator and the game balance that can be tuned
+/// without recompiling. Every setting has a default, the config file and `--set` only need to
+/// mention what they change.
+#[derive(Clone, Debug)]
+pub struct Config {
+    // actual size of the window
+    pub screen_width: i32,
+    pub screen_height: i32,
+    pub font_path: String,
+    /// frames-per-second maximum, 0 for no limit
+    pub limit_fps: i32,
+
+    // size of the map
+    pub map_width: i32,
+    pub map_height: i32,
+
+    // parameters for dungeon generator
+    pub generator: Generator,
+    pub room_min_size: i32,
+    pub room_max_size: i32,
+    /// how many rooms `Generator::Rooms` tries to place
+    pub max_rooms: i32,
+    /// how many times `Generator::Bsp` splits the map in two
+    pub bsp_depth: i32,
+    /// how likely each tile of `Generator::Caves` starts out as wall, in percent
+    pub cave_wall_chance: i32,
+    /// how many times the caves are smoothed out
+    pub cave_steps: i32,
+    /// whether caves cut off from the biggest one get a tunnel to it rather than filled in
+    pub cave_tunnels: bool,
+    /// how much of the map `Generator::Drunkard` digs out, in percent
+    pub drunkard_coverage: i32,
+    /// how many areas the levels without rooms spread monsters and items over
+    pub spawn_regions: i32,
+
+    pub fov_algorithm: FovAlgorithm,
+    pub fov_light_walls: bool,
+    /// how far the player sees, 0 for no limit
+    pub torch_radius: i32,
+
+    // panel below the map
+    pub panel_height: i32,
+    pub bar_width: i32,
+
+    // tile colors
+    pub color_dark_wall: Color,
+    pub color_light_wall: Color,
+    pub color_dark_ground: Color,
+    pub color_light_ground: Color,
+
+    pub templates_path: String,
+    pub keymap_path: String,
+    pub heal_amount: i32,
+    pub inventory_width: i32,
+    /// how many messages the log keeps, the oldest are forgotten
+    pub message_limit: i32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            screen_width: 80,
+            screen_height: 50,
+            font_path: "src/assets/arial_10x10.png".into(),
+            limit_fps: 20,
+            map_width: 80,
+            map_height: 43,
+            generator: Generator::Rooms,
+            room_min_size: 6,
+            room_max_size: 10,
+            max_rooms: 30,
+            bsp_depth: 4,
+            cave_wall_chance: 45,
+            cave_steps: 4,
+            cave_tunnels: true,
+            drunkard_coverage: 40,
+            spawn_regions: 12,
+            fov_algorithm: FovAlgorithm::Basic,
+            fov_light_walls: true,
+            torch_radius: 10,
+            panel_height: 7,
+            bar_width: 20,
+            color_dark_wall: Color::new(0, 0, 100),
+            color_light_wall: Color::new(130, 110, 50),
+            color_dark_ground: Color::new(50, 50, 150),
+            color_light_ground: Color::new(200, 180, 50),
+            templates_path: "src/assets/templates.toml".into(),
+            keymap_path: KEYMAP_PATH.into(),
+            heal_amount: 4,
+            inventory_width: 50,
+            message_limit: 500,
+        }
+    }
+}
+
+impl Config {
+    /// the defaults, overridden by the file at `path` and then by each `section.key=value`
+    /// override in turn. A missing file is only an error when `required`.
+    pub fn load(path: &str, required: bool, overrides: &[String]) -> Result<Self, DataError> {
+        let mut config = Config::default();
+
+        if required || Path::new(path).exists() {
+            config.read(&Document::load(path)?)?;
+        }
+        for text in overrides {
+            config.read(&override_document(text)?)?;
+        }
+
+        config.validate().map_err(|message| DataError {
+            path: "configuration".into(),
+            line: 0,
+            message,
+        })?;
+        Ok(config)
+    }
+
+    /// the first row of the panel, right below the map area
+    pub fn panel_y(&self) -> i32 {
+        self.screen_height - self.panel_height
+    }
+
+    /// the message log sits to the right of the bars
+    pub fn msg_x(&self) -> i32 {
+        self.bar_width + 2
+    }
+
+    pub fn msg_width(&self) -> i32 {
+        self.screen_width - self.bar_width - 2
+    }
+
+    pub fn msg_height(&self) -> i32 {
+        self.panel_height - 1
+    }
+
+    /// overwrite the settings the document mentions
+    fn read(&mut self, document: &Document) -> Result<(), DataError> {
+        if let Some(entry) = document.root().entries.first() {
+            return Err(document.error(
+                entry.line,
+                format!("`{}` must be inside a section, eg. [map]", entry.key),
+            ));
+        }
+
+        for table in &document.tables[1..] {
+            if table.array {
+                return Err(document.error(
+                    table.line,
+                    format!(
+                        "{} has to be [{}], every section comes once",
+                        table.header(),
+                        table.name
+                    ),
+                ));
+            }
+            let mut reader = TableReader::new(document, table, table.header());
+            match table.name.as_str() {
+                "screen" => {
+                    read_integer(&mut reader, "width", 1, &mut self.screen_width)?;
+                    read_integer(&mut reader, "height", 1, &mut self.screen_height)?;
+                    self.font_path = reader.string_or("font", &self.font_path)?;
+                    read_integer(&mut reader, "fps", 0, &mut self.limit_fps)?;
+                }
+                "map" => {
+                    read_integer(&mut reader, "width", 1, &mut self.map_width)?;
+                    read_integer(&mut reader, "height", 1, &mut self.map_height)?;
+                    if let Some(entry) = reader.optional("generator") {
+                        self.generator = generator_from(&reader, entry)?;
+                    }
+                    read_integer(&mut reader, "room_min_size", 3, &mut self.room_min_size)?;
+                    read_integer(&mut reader, "room_max_size", 3, &mut self.room_max_size)?;
+                    read_integer(&mut reader, "max_rooms", 1, &mut self.max_rooms)?;
+                    read_integer(&mut reader, "bsp_depth", 1, &mut self.bsp_depth)?;
+                    read_integer(
+                        &mut reader,
+                        "cave_wall_chance",
+                        0,
+                        &mut self.cave_wall_chance,
+                    )?;
+                    read_integer(&mut reader, "cave_steps", 0, &mut self.cave_steps)?;
+                    self.cave_tunnels = reader.boolean_or("cave_tunnels", self.cave_tunnels)?;
+                    read_integer(
+                        &mut reader,
+                        "drunkard_coverage",
+                        1,
+                        &mut self.drunkard_coverage,
+                    )?;
+                    read_integer(&mut reader, "spawn_regions", 1, &mut self.spawn_regions)?;
+                }
+                "fov" => {
+                    if let Some(entry) = reader.optional("algorithm") {
+                        self.fov_algorithm = fov_algorithm_from(&reader, entry)?;
+                    }
+                    self.fov_light_walls =
+                        reader.boolean_or("light_walls", self.fov_light_walls)?;
+                    read_integer(&mut reader, "torch_radius", 0, &mut self.torch_radius)?;
+                }
+                "panel" => {
+                    // the panel needs a row for each of its lines, the seed being the last
+                    read_integer(&mut reader, "height", 6, &mut self.panel_height)?;
+                    read_integer(&mut reader, "bar_width", 1, &mut self.bar_width)?;
+                }
+                "colors" => {
+                    read_color(&mut reader, "dark_wall", &mut self.color_dark_wall)?;
+                    read_color(&mut reader, "light_wall", &mut self.color_light_wall)?;
+                    read_color(&mut reader, "dark_ground", &mut self.color_dark_ground)?;
+                    read_color(&mut reader, "light_ground", &mut self.color_light_ground)?;
+                }
+                "game" => {
+                    self.templates_path = reader.string_or("templates", &self.templates_path)?;
+                    self.keymap_path = reader.string_or("keymap", &self.keymap_path)?;
+                    read_integer(&mut reader, "heal_amount", 1, &mut self.heal_amount)?;
+                    read_integer(&mut reader, "inventory_width", 1, &mut self.inventory_width)?;
+                    read_integer(&mut reader, "message_limit", 1, &mut self.message_limit)?;
+                }
+                _ => {
+                    return Err(document.error(
+                        table.line,
+                        format!(
+                            "unknown section {}, expected one of [screen], [map], [fov], \
+                             [panel], [colors] or [game]",
+                            table.header()
+                        ),
+                    ))
+                }
+            }
+            reader.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// the checks that involve more than one setting
+    fn validate(&self) -> Result<(), String> {
+        if self.map_width > self.screen_width
+            || self.map_height + self.panel_height > self.screen_height
+        {
+            return Err(format!(
+                "the {}x{} map doesn't fit on the {}x{} screen above the {} rows of the panel",
+                self.map_width,
+                self.map_height,
+                self.screen_width,
+                self.screen_height,
+                self.panel_height
+            ));
+        }
+        if self.room_min_size > self.room_max_size {
+            return Err(format!(
+                "room_min_size ({}) is bigger than room_max_size ({})",
+                self.room_min_size, self.room_max_size
+            ));
+        }
+        if self.room_max_size >= self.map_width || self.room_max_size >= self.map_height {
+            return Err(format!(
+                "rooms of up to {} tiles don't fit in the {}x{} map",
+                self.room_max_size, self.map_width, self.map_height
+            ));
+        }
+        if self.cave_wall_chance > 70 {
+            return Err(format!(
+                "a cave_wall_chance of {}% leaves too little floor, at most 70 works",
+                self.cave_wall_chance
+            ));
+        }
+        if self.drunkard_coverage > 80 {
+            return Err(format!(
+                "a drunkard_coverage of {}% takes too long to dig, at most 80 works",
+                self.drunkard_coverage
+            ));
+        }
+        if self.generator == Generator::Mixed && self.room_max_size >= self.map_width / 3 {
+            return Err(format!(
+                "rooms of up to {} tiles don't fit in a third of the {} tiles wide map, which \
+                 is all \"mixed\" may leave them",
+                self.room_max_size, self.map_width
+            ));
+        }
+        if self.msg_width() < 1 {
+            return Err(format!(
+                "the {} wide bars leave no room for messages on a {} wide screen",
+                self.bar_width, self.screen_width
+            ));
+        }
+        if self.inventory_width > self.screen_width {
+            return Err(format!(
+                "the {} wide inventory doesn't fit on a {} wide screen",
+                self.inventory_width, self.screen_width
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// a one-entry document for `section.key=value` from the command line
+fn override_document(text: &str) -> Result<Document, DataError> {
+    let path = format!("--set {}", text);
+    let error = |message: &str| DataError {
+        path: path.clone(),
+        line: 0,
+        message: message.into(),
+    };
+
+    let (name, value) = text
+        .split_once('=')
+        .ok_or_else(|| error("expected section.key=value"))?;
+    let (section, key) = name
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| error("expected section.key=value"))?;
+
+    // a bare word is taken as a string so names don't need quoting in the shell
+    let value = value.trim();
+    let value = Value::parse(value).unwrap_or_else(|_| Value::String(value.into()));
+
+    let root = Table {
+        name: String::new(),
+        array: false,
+        line: 0,
+        entries: vec![],
+    };
+    let table = Table {
+        name: section.into(),
+        array: false,
+        line: 0,
+        entries: vec![Entry {
+            key: key.into(),
+            value,
+            line: 0,
+        }],
+    };
+    Ok(Document {
+        path,
+        tables: vec![root, table],
+    })
+}
+
+fn read_integer(
+    reader: &mut TableReader,
+    key: &'static str,
+    minimum: i32,
+    value: &mut i32,
+) -> Result<(), DataError> {
+    let integer = reader.integer_or(key, i64::from(*value))?;
+    if integer < i64::from(minimum) || integer > i64::from(i32::MAX) {
+        let line = reader.required(key)?.line;
+        return Err(reader.error(line, format!("`{}` must be at least {}", key, minimum)));
+    }
+    *value = integer as i32;
+    Ok(())
+}
+
+fn read_color(
+    reader: &mut TableReader,
+    key: &'static str,
+    color: &mut Color,
+) -> Result<(), DataError> {
+    if let Some(entry) = reader.optional(key) {
+        *color = color_from(entry)
+            .ok_or_else(|| reader.expected(entry, "an array of three numbers 0-255"))?;
+    }
+    Ok(())
+}
+
+fn fov_algorithm_from(reader: &TableReader, entry: &Entry) -> Result<FovAlgorithm, DataError> {
+    use FovAlgorithm::*;
+    let name = match &entry.value {
+        Value::String(name) => name,
+        _ => return Err(reader.expected(entry, "a string")),
+    };
+    let algorithm = match name.as_str() {
+        "basic" => Basic,
+        "diamond" => Diamond,
+        "shadow" => Shadow,
+        "permissive-0" => Permissive0,
+        "permissive-1" => Permissive1,
+        "permissive-2" => Permissive2,
+        "permissive-3" => Permissive3,
+        "permissive-4" => Permissive4,
+        "permissive-5" => Permissive5,
+        "permissive-6" => Permissive6,
+        "permissive-7" => Permissive7,
+        "permissive-8" => Permissive8,
+        "restrictive" => Restrictive,
+        _ => {
+            return Err(reader.error(
+                entry.line,
+                format!(
+                    "unknown algorithm '{}', expected one of 'basic', 'diamond', 'shadow', \
+                     'permissive-0' to 'permissive-8' or 'restrictive'",
+                    name
+                ),
+            ))
+        }
+    };
+    Ok(algorithm)
+}
+
+fn generator_from(reader: &TableReader, entry: &Entry) -> Result<Generator, DataError> {
+    match &entry.value {
+        Value::String(name) if name == "rooms" => Ok(Generator::Rooms),
+        Value::String(name) if name == "bsp" => Ok(Generator::Bsp),
+        Value::String(name) if name == "caves" => Ok(Generator::Caves),
+        Value::String(name) if name == "drunkard" => Ok(Generator::Drunkard),
+        Value::String(name) if name == "mixed" => Ok(Generator::Mixed),
+        Value::String(name) => Err(reader.error(
+            entry.line,
+            format!(
+                "unknown generator '{}', expected 'rooms', 'bsp', 'caves', 'drunkard' or 'mixed'",
+                name
+            ),
+        )),
+        _ => Err(reader.expected(entry, "a string")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the defaults with the command line overrides applied
+    fn load(overrides: &[&str]) -> Result<Config, DataError> {
+        let overrides: Vec<String> = overrides.iter().map(|&text| text.into()).collect();
+        Config::load("no such config.toml", false, &overrides)
+    }
+
+    /// where the override went wrong and why
+    fn error(overrides: &[&str]) -> (String, String) {
+        let error = load(overrides).unwrap_err();
+        (error.path, error.message)
+    }
+
+    #[test]
+    fn overrides_change_the_settings() {
+        let config = load(&[
+            "map.generator=caves",
+            "fov.light_walls=false",
+            "map.width=60",
+        ])
+        .unwrap();
+        assert_eq!(config.generator, Generator::Caves);
+        assert!(!config.fov_light_walls);
+        assert_eq!(config.map_width, 60);
+        // the file is only optional when it isn't asked for
+        assert!(Config::load("no such config.toml", true, &[]).is_err());
+    }
+
+    #[test]
+    fn a_map_bigger_than_the_screen_is_refused() {
+        let (path, message) = error(&["map.width=81"]);
+        assert_eq!(path, "configuration");
+        assert_eq!(
+            message,
+            "the 81x43 map doesn't fit on the 80x50 screen above the 7 rows of the panel"
+        );
+        assert!(load(&["map.width=81", "screen.width=81"]).is_ok());
+    }
+
+    #[test]
+    fn bad_values_are_refused() {
+        assert_eq!(
+            error(&["map.width"]),
+            (
+                "--set map.width".into(),
+                "expected section.key=value".into()
+            )
+        );
+        assert_eq!(
+            error(&["map.width=0"]).1,
+            "[map]: `width` must be at least 1"
+        );
+        assert!(error(&["fov.algorithm=blurry"])
+            .1
+            .starts_with("[fov]: unknown algorithm 'blurry'"));
+    }
+
+    #[test]
+    fn unknown_sections_and_keys_are_refused() {
+        assert!(error(&["sound.volume=3"])
+            .1
+            .starts_with("unknown section [sound]"));
+        assert_eq!(
+            error(&["map.depth=3"]),
+            (
+                "--set map.depth=3".into(),
+                "[map]: unknown key `depth`".into()
+            )
+        );
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_refused() {
+        assert_eq!(
+            error(&["map.width=wide"]).1,
+            "[map]: `width` must be an integer, not a string"
+        );
+        assert_eq!(
+            error(&["fov.light_walls=3"]).1,
+            "[fov]: `light_walls` must be a boolean, not an integer"
+        );
+        assert_eq!(
+            error(&["colors.dark_wall=[0, 0]"]).1,
+            "[colors]: `dark_wall` must be an array of three numbers 0-255, not an array"
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use crate::domain::{can_step, is_blocked, Map};
+use crate::world::World;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// what an inverted map multiplies the steps by. The sign turns far into low, so rolling
+/// downhill leads away; the size is how much getting far away counts over getting away
+/// quickly, and above 1 running past the player into open space beats cowering in the
+/// nearest dead end
+const FLEE_FACTOR: f32 = -1.2;
+
+/// For every tile, how many steps away the nearest goal is. Monsters pick their step by
+/// rolling downhill: moving to the neighbour with the lowest value gets them to a goal.
+/// Maps can be inverted to run away from the goals instead, and summed with weights to
+/// balance several wishes.
+#[derive(Clone, Debug)]
+pub struct DijkstraMap {
+    width: i32,
+    height: i32,
+    /// infinite for walls and tiles no goal can be reached from
+    values: Vec<f32>,
+}
+
+impl DijkstraMap {
+    /// steps to the nearest of the goals, moving in all eight directions around walls and
+    /// their corners
+    pub fn new(map: &Map, goals: &[(i32, i32)]) -> Self {
+        let mut dijkstra = DijkstraMap::unreachable(map);
+        for &(x, y) in goals {
+            if dijkstra.in_map(x, y) && !map[x as usize][y as usize].blocked {
+                let index = dijkstra.index(x, y);
+                dijkstra.values[index] = 0.0;
+            }
+        }
+        dijkstra.settle(map);
+        dijkstra
+    }
+
+    /// a map that leads away from the goals: far tiles become the new lows, and settling it
+    /// again lets the way out run around corners rather than into them
+    pub fn invert(&self, map: &Map) -> Self {
+        let mut inverted = self.clone();
+        for value in inverted.values.iter_mut().filter(|value| value.is_finite()) {
+            *value *= FLEE_FACTOR;
+        }
+        inverted.settle(map);
+        inverted
+    }
+
+    /// this map plus `weight` times the other one
+    pub fn combine(&self, other: &DijkstraMap, weight: f32) -> Self {
+        let mut combined = self.clone();
+        for (value, other) in combined.values.iter_mut().zip(&other.values) {
+            *value += weight * other;
+        }
+        combined
+    }
+
+    pub fn value(&self, x: i32, y: i32) -> f32 {
+        if self.in_map(x, y) {
+            self.values[self.index(x, y)]
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    /// every tile exactly `value` away from the goals
+    pub fn tiles_at(&self, value: f32) -> Vec<(i32, i32)> {
+        (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .filter(|&(x, y)| self.value(x, y) == value)
+            .collect()
+    }
+
+    /// the free neighbour with the lowest value, if it's lower than where `from` is
+    pub fn downhill(&self, (x, y): (i32, i32), map: &Map, world: &World) -> Option<(i32, i32)> {
+        let mut best = None;
+        let mut best_value = self.value(x, y);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (next_x, next_y) = (x + dx, y + dy);
+                let value = self.value(next_x, next_y);
+                if value < best_value
+                    && can_step(x, y, dx, dy, map)
+                    && !is_blocked(next_x, next_y, map, world)
+                {
+                    best = Some((next_x, next_y));
+                    best_value = value;
+                }
+            }
+        }
+        best
+    }
+
+    fn unreachable(map: &Map) -> Self {
+        let width = map.len() as i32;
+        let height = map.first().map_or(0, |column| column.len() as i32);
+        DijkstraMap {
+            width,
+            height,
+            values: vec![f32::INFINITY; (width * height) as usize],
+        }
+    }
+
+    fn in_map(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (x * self.height + y) as usize
+    }
+
+    /// lower every tile to one more than its lowest neighbour, starting from the lowest tiles
+    fn settle(&mut self, map: &Map) {
+        let mut open: BinaryHeap<_> = self
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| value.is_finite())
+            .map(|(index, &value)| Lowest(value, index))
+            .collect();
+
+        while let Some(Lowest(value, index)) = open.pop() {
+            if value > self.values[index] {
+                // a lower value was found for it since this one was queued
+                continue;
+            }
+
+            let (x, y) = (index as i32 / self.height, index as i32 % self.height);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let (next_x, next_y) = (x + dx, y + dy);
+                    if !self.in_map(next_x, next_y) || !can_step(x, y, dx, dy, map) {
+                        continue;
+                    }
+                    let next = self.index(next_x, next_y);
+                    if value + 1.0 < self.values[next] {
+                        self.values[next] = value + 1.0;
+                        open.push(Lowest(value + 1.0, next));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A tile in the settling queue, ordered so the lowest value comes out of the heap first
+struct Lowest(f32, usize);
+
+impl PartialEq for Lowest {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Lowest {}
+
+impl PartialOrd for Lowest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Lowest {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .0
+            .total_cmp(&self.0)
+            .then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::map_from;
+
+    fn corridor() -> Map {
+        map_from(&[
+            "########", //
+            "#......#", //
+            "########", //
+        ])
+    }
+
+    fn row(dijkstra: &DijkstraMap, y: i32) -> Vec<f32> {
+        (0..dijkstra.width).map(|x| dijkstra.value(x, y)).collect()
+    }
+
+    #[test]
+    fn values_count_the_steps_to_the_nearest_goal() {
+        let map = map_from(&[
+            "######", //
+            "#....#", //
+            "#.##.#", //
+            "#....#", //
+            "######", //
+        ]);
+        let dijkstra = DijkstraMap::new(&map, &[(1, 1), (4, 3)]);
+        let inf = f32::INFINITY;
+        assert_eq!(row(&dijkstra, 1), vec![inf, 0.0, 1.0, 2.0, 2.0, inf]);
+        // no cutting the corners of the walls in the middle
+        assert_eq!(row(&dijkstra, 2), vec![inf, 1.0, inf, inf, 1.0, inf]);
+        assert_eq!(row(&dijkstra, 3), vec![inf, 2.0, 2.0, 1.0, 0.0, inf]);
+        assert_eq!(dijkstra.value(-1, 0), inf);
+        assert_eq!(dijkstra.tiles_at(2.0), vec![(1, 3), (2, 3), (3, 1), (4, 1)]);
+
+        // a goal in a wall is no goal
+        let dijkstra = DijkstraMap::new(&map, &[(0, 0)]);
+        assert!(dijkstra.tiles_at(0.0).is_empty());
+    }
+
+    #[test]
+    fn inverting_leads_away_from_the_goals() {
+        let map = corridor();
+        let world = World::new();
+        let towards = DijkstraMap::new(&map, &[(2, 1)]);
+        assert_eq!(towards.downhill((5, 1), &map, &world), Some((4, 1)));
+
+        let away = towards.invert(&map);
+        assert_eq!(away.downhill((3, 1), &map, &world), Some((4, 1)));
+        assert_eq!(away.downhill((6, 1), &map, &world), None);
+        // the far end is the lowest point, however steep the factor makes it
+        let lowest = (1..7).min_by(|&a, &b| away.value(a, 1).total_cmp(&away.value(b, 1)));
+        assert_eq!(lowest, Some(6));
+        assert_eq!(away.value(6, 1), 4.0 * FLEE_FACTOR);
+        assert_eq!(away.value(0, 1), f32::INFINITY);
+    }
+
+    #[test]
+    fn the_far_end_beats_a_nearer_dead_end() {
+        let map = map_from(&[
+            "############", //
+            "#..........#", //
+            "############", //
+        ]);
+        let away = DijkstraMap::new(&map, &[(3, 1)]).invert(&map);
+        assert!(away.value(10, 1) < away.value(1, 1));
+        // right next to the goal, the far end is worth running past it for
+        assert!(away.value(4, 1) < away.value(2, 1));
+        assert_eq!(away.downhill((3, 1), &map, &World::new()), Some((4, 1)));
+    }
+
+    #[test]
+    fn combining_weighs_the_maps() {
+        let map = corridor();
+        let left = DijkstraMap::new(&map, &[(1, 1)]);
+        let right = DijkstraMap::new(&map, &[(6, 1)]);
+        let combined = left.combine(&right, 0.5);
+        let inf = f32::INFINITY;
+        assert_eq!(
+            row(&combined, 1),
+            vec![inf, 2.5, 3.0, 3.5, 4.0, 4.5, 5.0, inf]
+        );
+    }
+
+    #[test]
+    fn rolling_downhill_steps_around_blockers() {
+        let map = map_from(&[
+            "#####", //
+            "#...#", //
+            "#...#", //
+            "#####", //
+        ]);
+        let mut world = World::new();
+        let blocker = world.spawn();
+        world.set_pos(blocker, 2, 1);
+        world.blocks.insert(blocker, ());
+
+        let dijkstra = DijkstraMap::new(&map, &[(1, 1)]);
+        assert_eq!(dijkstra.downhill((3, 1), &map, &world), Some((2, 2)));
+        assert_eq!(dijkstra.downhill((1, 1), &map, &world), None);
+    }
+}
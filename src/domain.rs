@@ -1,5 +1,13 @@
+use crate::config::Config;
+use crate::scheduler::{self, ATTACK_COST, MOVE_COST};
+use crate::templates::Templates;
+use crate::world::{Entity, World};
+use rand::rngs::StdRng;
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
 use tcod::colors::*;
-use tcod::console::{BackgroundFlag, Console};
+use tcod::map::Map as FovMap;
 
 /// A tile of the map and its properties
 #[derive(Clone, Debug)]
@@ -30,131 +38,372 @@ pub type Map = Vec<Vec<Tile>>;
 
 pub struct Game {
     pub map: Map,
+    /// what the player can currently see, kept in sync with `map`
+    pub fov: FovMap,
     pub messages: Messages,
-    pub inventory: Vec<Object>,
+    /// how deep the player is, starting at 1 for the first level
+    pub dungeon_level: u32,
+    /// the seed the dungeon was generated from, shown so a game can be replayed
+    pub seed: u64,
+    /// the single source of randomness for map generation, combat and AI
+    pub rng: StdRng,
+    /// the settings the game was started with, they aren't saved
+    pub config: Config,
+    /// the monsters and items new levels are populated with
+    pub templates: Templates,
 }
 
-/// This is a generic object: the player, a monster, an item, the stairs...
-/// It's always represented by a character on screen.
-#[derive(Clone, Debug)]
-pub struct Object {
+/// Where an entity is on the map; items in an inventory have none
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
     pub x: i32,
     pub y: i32,
-    pub char: char,
-    pub name: String,
+}
+
+impl Position {
+    /// return the distance to another position
+    pub fn distance_to(&self, other: &Position) -> f32 {
+        self.distance(other.x, other.y)
+    }
+
+    /// return the distance to some coordinates
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+}
+
+/// How an entity is drawn on the map
+#[derive(Clone, Copy, Debug)]
+pub struct Renderable {
+    pub glyph: char,
     pub color: Color,
-    pub blocks: bool,
-    pub alive: bool,
-    pub fighter: Option<Fighter>,
-    pub ai: Option<Ai>,
-    pub item: Option<Item>,
-}
-
-impl Object {
-    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
-        Object {
-            x,
-            y,
-            char,
+    /// keep drawing it once its tile has been explored, even when out of sight
+    pub always_visible: bool,
+}
+
+impl Renderable {
+    pub fn new(glyph: char, color: Color) -> Self {
+        Renderable {
+            glyph,
             color,
-            name: name.into(),
-            blocks,
-            alive: false, // default things to being non-alive
-            fighter: None,
-            ai: None,
-            item: None,
+            always_visible: false,
         }
     }
+}
 
-    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-        // a simple formula for attack damage
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
+/// The items an entity carries, in the order they were picked up
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    pub items: Vec<Entity>,
+}
 
-        let message = if damage > 0 {
-            target.take_damage(damage, game);
-            format!(
-                "{} attacks {} for {} hit points.",
-                self.name, target.name, damage
-            )
-        } else {
-            format!(
-                "{} attacks {} but it has no effect!",
-                self.name, target.name
-            )
-        };
+/// A pile of identical items that is carried and lies around as one entity; items without it
+/// never stack
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stack {
+    pub quantity: i32,
+}
 
-        game.messages.add(message, WHITE);
+/// how many items the entity stands for, 1 unless it's a stack
+pub fn quantity(item: Entity, world: &World) -> i32 {
+    world.stacks.get(item).map_or(1, |stack| stack.quantity)
+}
+
+/// the item's name, preceded by how many there are if it's more than one: "3 healing potions"
+pub fn item_label(item: Entity, world: &World) -> String {
+    match quantity(item, world) {
+        1 => world.name(item).into(),
+        quantity => format!("{} {}", quantity, plural(world.name(item))),
     }
+}
 
-    /// return the distance to another object
-    pub fn distance_to(&self, other: &Object) -> f32 {
-        let dx = other.x - self.x;
-        let dy = other.y - self.y;
-        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+/// how a message calls the item: "a healing potion" or "3 healing potions"
+pub fn item_amount(item: Entity, world: &World) -> String {
+    match quantity(item, world) {
+        1 => format!("a {}", world.name(item)),
+        _ => item_label(item, world),
     }
+}
+
+/// "healing potions", "scrolls of fireball"
+fn plural(name: &str) -> String {
+    let (noun, rest) = name.split_at(name.find(" of ").unwrap_or(name.len()));
+    let sibilant = ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|ending| noun.ends_with(ending));
+    let suffix = if sibilant { "es" } else { "s" };
+    format!("{}{}{}", noun, suffix, rest)
+}
 
-    /// set the color and then draw the character that represents this object at its position
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+/// take a single item off the stack as an entity of its own, or the item itself if it's alone
+pub fn take_one(item: Entity, world: &mut World) -> Entity {
+    match world.stacks.get_mut(item) {
+        Some(stack) if stack.quantity > 1 => {
+            stack.quantity -= 1;
+            let one = world.spawn();
+            world.names.insert(one, world.name(item).into());
+            if let Some(&renderable) = world.renderables.get(item) {
+                world.renderables.insert(one, renderable);
+            }
+            if let Some(&effect) = world.items.get(item) {
+                world.items.insert(one, effect);
+            }
+            world.stacks.insert(one, Stack { quantity: 1 });
+            one
+        }
+        _ => item,
     }
+}
 
-    /// heal by the given amount, without going over the maximum
-    pub(crate) fn heal(&mut self, amount: i32) {
-        if let Some(ref mut fighter) = self.fighter {
-            fighter.hp += amount;
-            if fighter.hp > fighter.max_hp {
-                fighter.hp = fighter.max_hp;
+/// add the item to the owner's inventory, onto the stack of the same kind if there's one there
+pub fn put_in_inventory(owner: Entity, item: Entity, world: &mut World) {
+    let same_kind =
+        world.inventory(owner).iter().copied().find(|&carried| {
+            world.stacks.contains(carried) && world.name(carried) == world.name(item)
+        });
+    match (world.stacks.get(item).copied(), same_kind) {
+        (Some(added), Some(carried)) => {
+            if let Some(stack) = world.stacks.get_mut(carried) {
+                stack.quantity += added.quantity;
+            }
+            world.despawn(item);
+        }
+        _ => {
+            if let Some(inventory) = world.inventories.get_mut(owner) {
+                inventory.items.push(item);
             }
         }
     }
+}
 
-    pub fn pos(&self) -> (i32, i32) {
-        (self.x, self.y)
+/// use up one of the owner's items: the item itself, or one of its stack
+pub fn use_up(owner: Entity, item: Entity, world: &mut World) {
+    match world.stacks.get_mut(item) {
+        Some(stack) if stack.quantity > 1 => stack.quantity -= 1,
+        _ => {
+            world.take_from_inventory(owner, item);
+            world.despawn(item);
+        }
     }
+}
 
-    pub fn set_pos(&mut self, x: i32, y: i32) {
-        self.x = x;
-        self.y = y;
+/// put the equipment on if nothing is worn in its slot yet, without a word; monsters dress
+/// themselves like this, the player picks what to wear
+pub fn wear_if_free(owner: Entity, item: Entity, world: &mut World) {
+    let slot = match world.equipment.get(item) {
+        Some(equipment) => equipment.slot,
+        None => return,
+    };
+    if get_equipped_in_slot(slot, owner, world).is_none() {
+        if let Some(equipment) = world.equipment.get_mut(item) {
+            equipment.equipped = true;
+        }
     }
+}
 
-    fn take_damage(&mut self, damage: i32, game: &mut Game) {
-        // apply damage if possible
-        if let Some(fighter) = self.fighter.as_mut() {
-            if damage > 0 {
-                fighter.hp -= damage;
-            }
+/// a new entity standing on the map, drawn as `glyph`
+pub fn spawn_at(
+    world: &mut World,
+    x: i32,
+    y: i32,
+    glyph: char,
+    name: &str,
+    color: Color,
+    blocks: bool,
+) -> Entity {
+    let entity = world.spawn();
+    world.set_pos(entity, x, y);
+    world.names.insert(entity, name.into());
+    world
+        .renderables
+        .insert(entity, Renderable::new(glyph, color));
+    if blocks {
+        world.blocks.insert(entity, ());
+    }
+    entity
+}
+
+pub fn attack(attacker: Entity, target: Entity, game: &mut Game, world: &mut World) {
+    scheduler::spend(attacker, ATTACK_COST, world);
+
+    // a simple formula for attack damage
+    let damage = power(attacker, world) - defense(target, world);
+
+    if damage > 0 {
+        game.messages.add(
+            format!(
+                "{} attacks {} for {} hit points.",
+                world.name(attacker),
+                world.name(target),
+                damage
+            ),
+            WHITE,
+        );
+
+        // the attacker earns the experience of whatever it kills
+        if let Some(xp) = take_damage(target, damage, game, world) {
+            gain_xp(attacker, xp, game, world);
         }
+    } else {
+        game.messages.add(
+            format!(
+                "{} attacks {} but it has no effect!",
+                world.name(attacker),
+                world.name(target)
+            ),
+            WHITE,
+        );
+    }
+}
 
-        // check for death and call the death function if needed
-        if let Some(fighter) = self.fighter {
-            if fighter.hp <= 0 {
-                self.alive = false;
-                fighter.on_death.callback(self, game);
-            }
+/// heal by the given amount, without going over the maximum
+pub fn heal(entity: Entity, amount: i32, world: &mut World) {
+    let max_hp = max_hp(entity, world);
+    if let Some(fighter) = world.fighters.get_mut(entity) {
+        fighter.hp = cmp::min(fighter.hp + amount, max_hp);
+    }
+}
+
+/// attack power: the fighter's base power plus the bonus of everything equipped
+pub fn power(entity: Entity, world: &World) -> i32 {
+    let base_power = world.fighters.get(entity).map_or(0, |f| f.base_power);
+    let bonus: i32 = get_all_equipped(entity, world)
+        .iter()
+        .map(|e| e.power_bonus)
+        .sum();
+    base_power + bonus
+}
+
+pub fn defense(entity: Entity, world: &World) -> i32 {
+    let base_defense = world.fighters.get(entity).map_or(0, |f| f.base_defense);
+    let bonus: i32 = get_all_equipped(entity, world)
+        .iter()
+        .map(|e| e.defense_bonus)
+        .sum();
+    base_defense + bonus
+}
+
+pub fn max_hp(entity: Entity, world: &World) -> i32 {
+    let base_max_hp = world.fighters.get(entity).map_or(0, |f| f.base_max_hp);
+    let bonus: i32 = get_all_equipped(entity, world)
+        .iter()
+        .map(|e| e.max_hp_bonus)
+        .sum();
+    base_max_hp + bonus
+}
+
+/// returns a list of the equipment worn from the entity's inventory
+pub fn get_all_equipped(entity: Entity, world: &World) -> Vec<Equipment> {
+    world
+        .inventory(entity)
+        .iter()
+        .filter_map(|&item| world.equipment.get(item).copied())
+        .filter(|equipment| equipment.equipped)
+        .collect()
+}
+
+/// equip the item and show a message about it
+pub fn equip(item: Entity, world: &mut World, messages: &mut Messages) {
+    match world.equipment.get_mut(item) {
+        Some(equipment) if !equipment.equipped => {
+            equipment.equipped = true;
+            let slot = equipment.slot;
+            messages.add(
+                format!("Equipped {} on {}.", world.name(item), slot),
+                LIGHT_GREEN,
+            );
         }
+        Some(_) => {}
+        None => messages.add(
+            format!(
+                "Can't equip {} because it's not an Equipment.",
+                world.name(item)
+            ),
+            RED,
+        ),
     }
 }
 
-/// move by the given amount, if not blocked
-pub fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
-    let (x, y) = objects[id].pos();
+/// unequip the item and show a message about it
+pub fn dequip(item: Entity, world: &mut World, messages: &mut Messages) {
+    match world.equipment.get_mut(item) {
+        Some(equipment) if equipment.equipped => {
+            equipment.equipped = false;
+            let slot = equipment.slot;
+            messages.add(
+                format!("Dequipped {} from {}.", world.name(item), slot),
+                LIGHT_YELLOW,
+            );
+        }
+        Some(_) => {}
+        None => messages.add(
+            format!(
+                "Can't dequip {} because it's not an Equipment.",
+                world.name(item)
+            ),
+            RED,
+        ),
+    }
+}
+
+/// credit the fighter with the experience of a kill; only the player hears about it
+pub fn gain_xp(entity: Entity, xp: i32, game: &mut Game, world: &mut World) {
+    if let Some(fighter) = world.fighters.get_mut(entity) {
+        fighter.xp += xp;
+        if entity == world.player {
+            game.messages
+                .add(format!("You gain {} experience points.", xp), ORANGE);
+        }
+    }
+}
+
+/// returns the experience points it was worth if this killed it
+pub fn take_damage(entity: Entity, damage: i32, game: &mut Game, world: &mut World) -> Option<i32> {
+    // apply damage if possible
+    let fighter = world.fighters.get_mut(entity)?;
+    if damage > 0 {
+        fighter.hp -= damage;
+    }
+
+    // check for death and call the death function if needed
+    let fighter = *fighter;
+    if fighter.hp <= 0 {
+        fighter.on_death.callback(entity, game, world);
+        return Some(fighter.xp);
+    }
 
-    if !is_blocked(x + dx, y + dy, map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
+    None
+}
+
+/// move by the given amount, if not blocked; bumping into something takes as long as a step
+pub fn move_by(entity: Entity, dx: i32, dy: i32, map: &Map, world: &mut World) {
+    scheduler::spend(entity, MOVE_COST, world);
+    if let Some((x, y)) = world.pos(entity) {
+        if can_step(x, y, dx, dy, map) && !is_blocked(x + dx, y + dy, map, world) {
+            world.set_pos(entity, x + dx, y + dy);
+        }
     }
 }
 
-pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+/// whether the walls allow a step from (x, y) by (dx, dy), ignoring who stands where. A
+/// diagonal step can't cut the corner of a wall: both tiles beside it have to be open too.
+/// Everything that moves or plans a route goes by this, the player and monsters alike.
+pub fn can_step(x: i32, y: i32, dx: i32, dy: i32, map: &Map) -> bool {
+    let open = |x: i32, y: i32| !map[x as usize][y as usize].blocked;
+    open(x + dx, y + dy) && (dx == 0 || dy == 0 || (open(x + dx, y) && open(x, y + dy)))
+}
+
+pub fn is_blocked(x: i32, y: i32, map: &Map, world: &World) -> bool {
     // first test the map tile
     if map[x as usize][y as usize].blocked {
         return true;
     }
 
-    // now check for any blocking objects
-    objects
+    // now check for any blocking entities
+    world
+        .blocks
         .iter()
-        .any(|object| object.blocks && object.pos() == (x, y))
+        .any(|(entity, _)| world.pos(entity) == Some((x, y)))
 }
 
 /// A rectangle on the map, used to characterize a room.
@@ -190,6 +439,22 @@ impl Rect {
     }
 }
 
+/// A value that takes effect from a given dungeon level onwards
+#[derive(Clone, Copy, Debug)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
+}
+
+/// returns a value that depends on level; the table specifies what value occurs after each level, default is 0
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
 #[derive(Debug, PartialEq)]
 pub enum PlayerAction {
     TookTurn,
@@ -200,20 +465,34 @@ pub enum PlayerAction {
 // combat-related properties and methods (monster, player, NPC)
 #[derive(Clone, Copy, Debug)]
 pub struct Fighter {
-    pub max_hp: i32,
+    // base stats, before any equipment bonus; see `power` and friends
+    pub base_max_hp: i32,
     pub hp: i32,
-    pub defense: i32,
-    pub power: i32,
-    on_death: DeathCallback,
+    pub base_defense: i32,
+    pub base_power: i32,
+    /// experience earned so far, or given to whoever kills this fighter
+    pub xp: i32,
+    /// character level, only gained by the player
+    pub level: i32,
+    pub on_death: DeathCallback,
 }
 
 impl Fighter {
-    pub fn new(defense: i32, hp: i32, max_hp: i32, power: i32, on_death: DeathCallback) -> Self {
+    pub fn new(
+        base_defense: i32,
+        hp: i32,
+        base_max_hp: i32,
+        base_power: i32,
+        xp: i32,
+        on_death: DeathCallback,
+    ) -> Self {
         Fighter {
-            defense,
+            base_defense,
             hp,
-            max_hp,
-            power,
+            base_max_hp,
+            base_power,
+            xp,
+            level: 1,
             on_death,
         }
     }
@@ -226,68 +505,349 @@ pub enum DeathCallback {
 }
 
 impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
+    fn callback(self, entity: Entity, game: &mut Game, world: &mut World) {
         use DeathCallback::*;
         let callback = match self {
             Player => player_death,
             Monster => monster_death,
         };
-        callback(object, game);
+        callback(entity, game, world);
     }
 }
 
-fn player_death(player: &mut Object, game: &mut Game) {
+fn player_death(player: Entity, game: &mut Game, world: &mut World) {
     // the game ended
     game.messages.add("You died!", RED);
 
     // for added effect, transform the player into a corpse
-    player.char = '%';
-    player.color = DARK_RED;
+    if let Some(renderable) = world.renderables.get_mut(player) {
+        renderable.glyph = '%';
+        renderable.color = DARK_RED;
+    }
 }
 
-fn monster_death(monster: &mut Object, game: &mut Game) {
+fn monster_death(monster: Entity, game: &mut Game, world: &mut World) {
     // transform it into a corpse
     game.messages
-        .add(format!("{} is dead!", monster.name), ORANGE);
-    monster.char = '%';
-    monster.color = DARK_RED;
-    monster.blocks = false;
-    monster.fighter = None;
-    monster.ai = None;
-    monster.name = format!("remains of {}", monster.name);
+        .add(format!("{} is dead!", world.name(monster)), ORANGE);
+    if let Some(renderable) = world.renderables.get_mut(monster) {
+        renderable.glyph = '%';
+        renderable.color = DARK_RED;
+    }
+    // whatever it carried falls where it died
+    if let (Some((x, y)), Some(inventory)) = (world.pos(monster), world.inventories.remove(monster))
+    {
+        if !inventory.items.is_empty() {
+            let dropped: Vec<_> = inventory
+                .items
+                .iter()
+                .map(|&item| item_amount(item, world))
+                .collect();
+            game.messages
+                .add(format!("It drops {}.", dropped.join(", ")), ORANGE);
+        }
+        for item in inventory.items {
+            if let Some(equipment) = world.equipment.get_mut(item) {
+                equipment.equipped = false;
+            }
+            world.set_pos(item, x, y);
+        }
+    }
+    world.blocks.remove(monster);
+    world.fighters.remove(monster);
+    world.ais.remove(monster);
+    world.actors.remove(monster);
+    world.paths.remove(monster);
+    let name = format!("remains of {}", world.name(monster));
+    world.names.insert(monster, name);
 }
 
 #[derive(Clone, Debug)]
 pub enum Ai {
     Basic,
+    /// stumbles around at random, then goes back to its previous behaviour
+    Confused {
+        previous_ai: Box<Ai>,
+        num_turns: i32,
+    },
+    /// chases like `Basic`, but runs from the player while its HP is under `threshold`
+    /// percent of its maximum, fighting back only when cornered
+    Fleeing {
+        threshold: i32,
+    },
+    /// shoots at the player from up to `range` steps away, backing off when they get close
+    Ranged {
+        range: i32,
+    },
+    /// roams toward the parts of the dungeon the player hasn't explored until it spots them
+    Wanderer,
 }
 
+/// A line of the message log
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub text: String,
+    pub color: Color,
+    /// how many times in a row it was said
+    pub count: u32,
+}
+
+impl Message {
+    /// the text as shown, with the number of repeats if there were any
+    pub fn display(&self) -> String {
+        if self.count > 1 {
+            format!("{} (x{})", self.text, self.count)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+/// The message log, oldest first. Only the latest `limit` messages are kept, and a message
+/// that repeats the previous one only raises its count.
 pub struct Messages {
-    pub messages: Vec<(String, Color)>,
+    messages: VecDeque<Message>,
+    limit: usize,
 }
 
 impl Messages {
-    pub fn new() -> Self {
-        Self { messages: vec![] }
+    pub fn new(limit: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            limit,
+        }
     }
 
-    /// add the new message as a tuple with the text and the color
+    /// add the new message with its color, unless it's the same as the last one
     pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color));
+        let text = message.into();
+        match self.messages.back_mut() {
+            Some(last) if last.text == text && last.color == color => last.count += 1,
+            _ => self.messages.push_back(Message {
+                text,
+                color,
+                count: 1,
+            }),
+        }
+        self.forget_oldest();
+    }
+
+    /// add a message that was already repeated `count` times, eg. from the save file
+    pub fn push(&mut self, message: Message) {
+        self.messages.push_back(message);
+        self.forget_oldest();
+    }
+
+    /// change how many messages are kept, forgetting the oldest ones if there are too many
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.forget_oldest();
     }
 
     /// create a double-ended iterator over the messages
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
         self.messages.iter()
     }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    fn forget_oldest(&mut self) {
+        while self.messages.len() > self.limit {
+            self.messages.pop_front();
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Item {
     Heal,
+    /// worn rather than consumed, see `Equipment`
+    Equip,
+    Lightning,
+    Fireball,
+    Confuse,
+    /// doubles the drinker's speed for a while
+    Haste,
+    /// halves the drinker's speed for a while
+    Slow,
+}
+
+impl Item {
+    /// potions break when thrown, splashing whoever they hit
+    pub fn shatters(self) -> bool {
+        matches!(self, Item::Heal | Item::Haste | Item::Slow)
+    }
 }
 
 pub enum UseResult {
     UsedUp,
+    UsedAndKept,
     Cancelled,
 }
+
+/// Where an equipment is worn; only one item fits in each slot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slot {
+    MainHand,
+    OffHand,
+    Head,
+    Body,
+    Ring,
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Slot::MainHand => write!(f, "main hand"),
+            Slot::OffHand => write!(f, "off hand"),
+            Slot::Head => write!(f, "head"),
+            Slot::Body => write!(f, "body"),
+            Slot::Ring => write!(f, "ring"),
+        }
+    }
+}
+
+/// An item that can be equipped, yielding bonuses
+#[derive(Clone, Copy, Debug)]
+pub struct Equipment {
+    pub slot: Slot,
+    pub equipped: bool,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub max_hp_bonus: i32,
+}
+
+impl Equipment {
+    pub fn new(slot: Slot, power_bonus: i32, defense_bonus: i32, max_hp_bonus: i32) -> Self {
+        Equipment {
+            slot,
+            equipped: false,
+            power_bonus,
+            defense_bonus,
+            max_hp_bonus,
+        }
+    }
+}
+
+/// whatever the entity has equipped in the slot, if anything
+pub fn get_equipped_in_slot(slot: Slot, entity: Entity, world: &World) -> Option<Entity> {
+    world.inventory(entity).iter().copied().find(|&item| {
+        world
+            .equipment
+            .get(item)
+            .is_some_and(|e| e.equipped && e.slot == slot)
+    })
+}
+
+/// a map drawn as rows of text, `#` for walls and anything else for floor
+#[cfg(test)]
+pub fn map_from(rows: &[&str]) -> Map {
+    let width = rows[0].len();
+    (0..width)
+        .map(|x| {
+            rows.iter()
+                .map(|row| match row.as_bytes()[x] {
+                    b'#' => Tile::wall(),
+                    _ => Tile::empty(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a player with an empty inventory
+    fn player() -> World {
+        let mut world = World::new();
+        world.player = world.spawn();
+        world.inventories.insert(world.player, Inventory::default());
+        world
+    }
+
+    /// an item lying nowhere, stacking like the templates make it
+    fn item(name: &str, stackable: bool, world: &mut World) -> Entity {
+        let item = world.spawn();
+        world.names.insert(item, name.into());
+        world.items.insert(item, Item::Heal);
+        if stackable {
+            world.stacks.insert(item, Stack { quantity: 1 });
+        }
+        item
+    }
+
+    #[test]
+    fn identical_items_pile_up() {
+        let mut world = player();
+        let owner = world.player;
+        let first = item("healing potion", true, &mut world);
+        let second = item("healing potion", true, &mut world);
+        let scroll = item("scroll of fireball", true, &mut world);
+        put_in_inventory(owner, first, &mut world);
+        put_in_inventory(owner, second, &mut world);
+        put_in_inventory(owner, scroll, &mut world);
+
+        assert_eq!(world.inventory(owner), &[first, scroll]);
+        assert_eq!(quantity(first, &world), 2);
+        assert!(!world.contains(second));
+    }
+
+    #[test]
+    fn taking_one_leaves_the_rest() {
+        let mut world = player();
+        let owner = world.player;
+        let potions = item("healing potion", true, &mut world);
+        world.stacks.insert(potions, Stack { quantity: 3 });
+        put_in_inventory(owner, potions, &mut world);
+
+        let one = take_one(potions, &mut world);
+        assert_ne!(one, potions);
+        assert_eq!((quantity(one, &world), quantity(potions, &world)), (1, 2));
+        assert_eq!(world.name(one), "healing potion");
+        assert_eq!(world.items.get(one), Some(&Item::Heal));
+        assert_eq!(world.inventory(owner), &[potions]);
+
+        // the last one is the stack itself
+        world.stacks.insert(potions, Stack { quantity: 1 });
+        assert_eq!(take_one(potions, &mut world), potions);
+    }
+
+    #[test]
+    fn equipment_never_stacks() {
+        let mut world = player();
+        let owner = world.player;
+        let swords: Vec<_> = (0..2)
+            .map(|_| {
+                let sword = item("sword", false, &mut world);
+                world
+                    .equipment
+                    .insert(sword, Equipment::new(Slot::MainHand, 3, 0, 0));
+                put_in_inventory(owner, sword, &mut world);
+                sword
+            })
+            .collect();
+
+        assert_eq!(world.inventory(owner), swords.as_slice());
+        assert_eq!(take_one(swords[0], &mut world), swords[0]);
+    }
+
+    #[test]
+    fn labels_count_the_items() {
+        let mut world = player();
+        let potions = item("healing potion", true, &mut world);
+        assert_eq!(item_label(potions, &world), "healing potion");
+        assert_eq!(item_amount(potions, &world), "a healing potion");
+
+        world.stacks.insert(potions, Stack { quantity: 3 });
+        assert_eq!(item_label(potions, &world), "3 healing potions");
+        assert_eq!(item_amount(potions, &world), "3 healing potions");
+
+        assert_eq!(plural("scroll of fireball"), "scrolls of fireball");
+        assert_eq!(plural("torch"), "torches");
+        assert_eq!(plural("box of matches"), "boxes of matches");
+    }
+}
@@ -0,0 +1,187 @@
+use crate::domain::Messages;
+use crate::render::{starts_text, wrap_text, Renderer};
+use std::cmp;
+use tcod::colors::*;
+use tcod::input::{Event, KeyCode};
+use tcod::TextAlignment;
+
+const MATCH_COLOR: Color = DARKEST_AMBER;
+
+/// Show every message the log still has over the whole screen, the latest at the bottom.
+/// The arrow keys, PageUp/PageDown and the mouse wheel scroll, `/` searches and `n`/`N` jump
+/// to the older/newer match; Escape or any other key closes it.
+pub fn show_history(messages: &Messages, renderer: &mut impl Renderer) {
+    let (width, height) = renderer.size();
+    // a row for the title and one for the keys or the search
+    let page_height = cmp::max(height - 2, 1) as usize;
+
+    // every message wrapped to the screen, each line with the message it belongs to
+    let texts: Vec<String> = messages
+        .iter()
+        .map(|message| message.display().to_lowercase())
+        .collect();
+    let lines: Vec<(String, Color, usize)> = messages
+        .iter()
+        .enumerate()
+        .flat_map(|(index, message)| {
+            wrap_text(&message.display(), width)
+                .into_iter()
+                .map(move |line| (line, message.color, index))
+        })
+        .collect();
+    let max_top = lines.len().saturating_sub(page_height);
+
+    let mut top = max_top;
+    let mut query = String::new();
+    let mut typing = false;
+    let mut status = String::new();
+
+    loop {
+        let matches =
+            |line: usize| !query.is_empty() && texts[lines[line].2].contains(&query.to_lowercase());
+
+        renderer.clear();
+        let title = format!("Message history, {} messages", messages.len());
+        renderer.print(0, 0, &title, YELLOW, TextAlignment::Left);
+        for (line, (text, color, _)) in lines.iter().enumerate().skip(top).take(page_height) {
+            let y = (line - top) as i32 + 1;
+            if matches(line) {
+                renderer.fill_background(0, y, width, 1, MATCH_COLOR);
+            }
+            renderer.print(0, y, text, *color, TextAlignment::Left);
+        }
+        let footer = if typing {
+            format!("Search: {}_", query)
+        } else if !status.is_empty() {
+            status.clone()
+        } else {
+            "arrows to scroll, / to search, n/N for the older/newer match, Escape to close".into()
+        };
+        renderer.print(0, height - 1, &footer, LIGHT_GREY, TextAlignment::Left);
+        renderer.flush();
+
+        // the first line of a match older than the ones on screen, or the last of a newer one
+        let older = (0..top).rev().find(|&line| matches(line)).map(|line| {
+            let message = lines[line].2;
+            (0..=line)
+                .rev()
+                .take_while(|&earlier| lines[earlier].2 == message)
+                .last()
+                .unwrap_or(line)
+        });
+        let newer = (top + page_height..lines.len()).find(|&line| matches(line));
+        let latest = (0..lines.len()).rev().find(|&line| matches(line));
+        // put the line on screen, as low as possible
+        let show = |line: usize| cmp::min((line + 1).saturating_sub(page_height), max_top);
+
+        status.clear();
+        match renderer.wait_for_event() {
+            Event::Key(key) if typing => match key.code {
+                KeyCode::Enter | KeyCode::NumPadEnter => {
+                    typing = false;
+                    match latest {
+                        Some(line) => top = show(line),
+                        None => status = format!("Nothing matches '{}'", query),
+                    }
+                }
+                KeyCode::Escape => {
+                    typing = false;
+                    query.clear();
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Text => query.push_str(key.text()),
+                _ => {}
+            },
+            Event::Key(key) => match key.code {
+                KeyCode::Up => top = top.saturating_sub(1),
+                KeyCode::Down => top = cmp::min(top + 1, max_top),
+                KeyCode::PageUp => top = top.saturating_sub(page_height),
+                KeyCode::PageDown => top = cmp::min(top + page_height, max_top),
+                KeyCode::Home => top = 0,
+                KeyCode::End => top = max_top,
+                KeyCode::Text => match key.text() {
+                    "/" => {
+                        typing = true;
+                        query.clear();
+                    }
+                    "n" | "N" if query.is_empty() => status = "Press / to search first".into(),
+                    "n" => match older {
+                        Some(line) => top = line,
+                        None => status = "No older match".into(),
+                    },
+                    "N" => match newer {
+                        Some(line) => top = show(line),
+                        None => status = "No newer match".into(),
+                    },
+                    _ => return,
+                },
+                _ if starts_text(key) => {}
+                _ => return,
+            },
+            Event::Mouse(mouse) => {
+                if mouse.wheel_up {
+                    top = top.saturating_sub(3);
+                } else if mouse.wheel_down {
+                    top = cmp::min(top + 3, max_top);
+                } else if mouse.rbutton_pressed {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{scripted, GridRenderer};
+
+    #[test]
+    fn a_slash_typed_with_shift_and_a_digit_starts_a_search() {
+        let mut messages = Messages::new(10);
+        messages.add("You hit the orc.", WHITE);
+        messages.add("The troll hits you.", WHITE);
+
+        // `/` as typed on layouts that put it on Shift+7
+        let mut grid = GridRenderer::new(40, 10);
+        grid.events.extend(vec![
+            scripted::key(KeyCode::Shift),
+            scripted::key(KeyCode::Number7),
+            scripted::text('/'),
+        ]);
+        grid.events.extend(scripted::typed("elf"));
+        grid.events.push_back(scripted::key(KeyCode::Enter));
+        show_history(&messages, &mut grid);
+
+        let footer = grid
+            .snapshot()
+            .lines()
+            .last()
+            .unwrap()
+            .trim_end()
+            .to_string();
+        assert_eq!(footer, "Nothing matches 'elf'");
+    }
+
+    #[test]
+    fn a_search_jumps_to_the_match() {
+        let mut messages = Messages::new(20);
+        messages.add("You hit the orc.", WHITE);
+        for turn in 0..10 {
+            messages.add(format!("Turn {}", turn), WHITE);
+        }
+
+        // five lines fit between the title and the footer
+        let mut grid = GridRenderer::new(40, 7);
+        grid.events.extend(scripted::typed("/orc"));
+        grid.events.push_back(scripted::key(KeyCode::Enter));
+        show_history(&messages, &mut grid);
+
+        let snapshot = grid.snapshot();
+        let lines: Vec<_> = snapshot.lines().collect();
+        assert_eq!(lines[1].trim_end(), "You hit the orc.");
+        assert_eq!(grid.cell(0, 1).background, MATCH_COLOR);
+    }
+}
@@ -0,0 +1,550 @@
+//! Which key does what. The game only ever deals in `Action`s; the keymap turns the keys the
+//! player presses into them. Every action has default keys, and the keymap file only needs
+//! to mention the actions it rebinds.
+
+use crate::toml::{DataError, Document, Entry, Value};
+use std::path::Path;
+use tcod::input::{Key, KeyCode};
+
+/// where the keymap is read from unless the config says otherwise
+pub const KEYMAP_PATH: &str = "keymap.toml";
+
+/// Something the player can ask for, whatever key it's bound to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    MoveNorth,
+    MoveSouth,
+    MoveWest,
+    MoveEast,
+    MoveNorthWest,
+    MoveNorthEast,
+    MoveSouthWest,
+    MoveSouthEast,
+    Wait,
+    PickUp,
+    Drop,
+    Throw,
+    Inventory,
+    Descend,
+    Look,
+    History,
+    Help,
+    Fullscreen,
+    Exit,
+}
+
+impl Action {
+    /// every action, in the order the help screen lists them
+    pub const ALL: [Action; 19] = [
+        Action::MoveNorth,
+        Action::MoveSouth,
+        Action::MoveWest,
+        Action::MoveEast,
+        Action::MoveNorthWest,
+        Action::MoveNorthEast,
+        Action::MoveSouthWest,
+        Action::MoveSouthEast,
+        Action::Wait,
+        Action::PickUp,
+        Action::Drop,
+        Action::Throw,
+        Action::Inventory,
+        Action::Descend,
+        Action::Look,
+        Action::History,
+        Action::Help,
+        Action::Fullscreen,
+        Action::Exit,
+    ];
+
+    /// how the action is called in the keymap file
+    pub fn name(self) -> &'static str {
+        use Action::*;
+        match self {
+            MoveNorth => "move_north",
+            MoveSouth => "move_south",
+            MoveWest => "move_west",
+            MoveEast => "move_east",
+            MoveNorthWest => "move_north_west",
+            MoveNorthEast => "move_north_east",
+            MoveSouthWest => "move_south_west",
+            MoveSouthEast => "move_south_east",
+            Wait => "wait",
+            PickUp => "pick_up",
+            Drop => "drop",
+            Throw => "throw",
+            Inventory => "inventory",
+            Descend => "descend",
+            Look => "look",
+            History => "history",
+            Help => "help",
+            Fullscreen => "fullscreen",
+            Exit => "exit",
+        }
+    }
+
+    /// what the action does, for the help screen
+    pub fn description(self) -> &'static str {
+        use Action::*;
+        match self {
+            MoveNorth => "move or attack north",
+            MoveSouth => "move or attack south",
+            MoveWest => "move or attack west",
+            MoveEast => "move or attack east",
+            MoveNorthWest => "move or attack north-west",
+            MoveNorthEast => "move or attack north-east",
+            MoveSouthWest => "move or attack south-west",
+            MoveSouthEast => "move or attack south-east",
+            Wait => "wait a turn",
+            PickUp => "pick up an item",
+            Drop => "drop an item",
+            Throw => "throw an item",
+            Inventory => "use an item",
+            Descend => "go down the stairs",
+            Look => "look at what's in sight",
+            History => "read the message history",
+            Help => "show this help",
+            Fullscreen => "toggle fullscreen",
+            Exit => "save and quit",
+        }
+    }
+
+    /// the step a movement action takes, (0, 0) for waiting
+    pub fn direction(self) -> Option<(i32, i32)> {
+        use Action::*;
+        match self {
+            MoveNorth => Some((0, -1)),
+            MoveSouth => Some((0, 1)),
+            MoveWest => Some((-1, 0)),
+            MoveEast => Some((1, 0)),
+            MoveNorthWest => Some((-1, -1)),
+            MoveNorthEast => Some((1, -1)),
+            MoveSouthWest => Some((-1, 1)),
+            MoveSouthEast => Some((1, 1)),
+            Wait => Some((0, 0)),
+            _ => None,
+        }
+    }
+
+    /// the keys bound to the action unless the keymap file says otherwise: the arrow keys,
+    /// the numpad with or without num lock, and the vi-keys
+    fn default_keys(self) -> &'static [&'static str] {
+        use Action::*;
+        match self {
+            MoveNorth => &["Up", "NumPad8", "k"],
+            MoveSouth => &["Down", "NumPad2", "j"],
+            MoveWest => &["Left", "NumPad4", "h"],
+            MoveEast => &["Right", "NumPad6", "l"],
+            MoveNorthWest => &["Home", "NumPad7", "y"],
+            MoveNorthEast => &["PageUp", "NumPad9", "u"],
+            MoveSouthWest => &["End", "NumPad1", "b"],
+            MoveSouthEast => &["PageDown", "NumPad3", "n"],
+            Wait => &["NumPad5", "."],
+            PickUp => &["g"],
+            Drop => &["d"],
+            Throw => &["t"],
+            Inventory => &["i"],
+            Descend => &[">"],
+            Look => &["x"],
+            History => &["m"],
+            Help => &["?"],
+            Fullscreen => &["Alt+Enter"],
+            Exit => &["Escape"],
+        }
+    }
+}
+
+/// A key with the modifiers that have to be held along with it
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Binding {
+    key: BoundKey,
+    alt: bool,
+    ctrl: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BoundKey {
+    /// a key that doesn't type anything, like the arrows
+    Code(KeyCode),
+    /// a key by the character it types, so `G` is shift and g
+    Text(char),
+}
+
+/// the names of the keys that don't type a character
+const KEY_NAMES: [(&str, KeyCode); 41] = [
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("Enter", KeyCode::Enter),
+    ("Escape", KeyCode::Escape),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Space", KeyCode::Spacebar),
+    ("NumPad0", KeyCode::NumPad0),
+    ("NumPad1", KeyCode::NumPad1),
+    ("NumPad2", KeyCode::NumPad2),
+    ("NumPad3", KeyCode::NumPad3),
+    ("NumPad4", KeyCode::NumPad4),
+    ("NumPad5", KeyCode::NumPad5),
+    ("NumPad6", KeyCode::NumPad6),
+    ("NumPad7", KeyCode::NumPad7),
+    ("NumPad8", KeyCode::NumPad8),
+    ("NumPad9", KeyCode::NumPad9),
+    ("NumPadEnter", KeyCode::NumPadEnter),
+    ("NumPadAdd", KeyCode::NumPadAdd),
+    ("NumPadSubtract", KeyCode::NumPadSubtract),
+    ("NumPadDecimal", KeyCode::NumPadDecimal),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+];
+
+impl Binding {
+    /// read a key name like `k`, `NumPad8` or `Alt+Enter`
+    fn parse(name: &str) -> Option<Self> {
+        let mut rest = name;
+        let (mut alt, mut ctrl) = (false, false);
+        loop {
+            if let Some(after) = rest.strip_prefix("Alt+").filter(|after| !after.is_empty()) {
+                alt = true;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("Ctrl+").filter(|after| !after.is_empty())
+            {
+                ctrl = true;
+                rest = after;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(glyph), None) if !glyph.is_whitespace() => BoundKey::Text(glyph),
+            _ => BoundKey::Code(KEY_NAMES.iter().find(|(key, _)| *key == rest)?.1),
+        };
+        Some(Binding { key, alt, ctrl })
+    }
+
+    fn matches(&self, key: Key) -> bool {
+        let pressed = match self.key {
+            BoundKey::Code(code) => key.code == code,
+            BoundKey::Text(glyph) => {
+                key.code == KeyCode::Text && key.text().chars().eq(std::iter::once(glyph))
+            }
+        };
+        pressed && key.alt == self.alt && key.ctrl == self.ctrl
+    }
+
+    /// the name the keymap file uses for the key
+    fn name(&self) -> String {
+        let key = match self.key {
+            BoundKey::Code(code) => KEY_NAMES
+                .iter()
+                .find(|(_, named)| *named == code)
+                .map_or("?", |(name, _)| name)
+                .to_string(),
+            BoundKey::Text(glyph) => glyph.to_string(),
+        };
+        let alt = if self.alt { "Alt+" } else { "" };
+        let ctrl = if self.ctrl { "Ctrl+" } else { "" };
+        format!("{}{}{}", ctrl, alt, key)
+    }
+}
+
+/// The keys bound to each action; a key never does two things
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .flat_map(|&action| {
+                action
+                    .default_keys()
+                    .iter()
+                    .map(move |name| (Binding::parse(name).unwrap(), action))
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// the default keys, with the actions the file at `path` mentions bound to the keys it
+    /// gives instead. A missing file is only an error when `required`.
+    pub fn load(path: &str, required: bool) -> Result<Self, DataError> {
+        if !required && !Path::new(path).exists() {
+            return Ok(Keymap::default());
+        }
+        Self::read(&Document::load(path)?)
+    }
+
+    fn read(document: &Document) -> Result<Self, DataError> {
+        let mut keymap = Keymap::default();
+        if let Some(table) = document.tables.get(1) {
+            return Err(document.error(
+                table.line,
+                "the keymap has no sections, bind the actions at the top of the file",
+            ));
+        }
+
+        // the line each rebound key comes from, to point at it if it clashes
+        let mut rebound = vec![];
+        for entry in &document.root().entries {
+            let action = Action::ALL
+                .iter()
+                .copied()
+                .find(|action| action.name() == entry.key)
+                .ok_or_else(|| {
+                    document.error(
+                        entry.line,
+                        format!("unknown action `{}`, {}", entry.key, expected_actions()),
+                    )
+                })?;
+
+            keymap.bindings.retain(|&(_, bound)| bound != action);
+            for binding in bindings_from(document, entry)? {
+                keymap.bindings.push((binding, action));
+                rebound.push((binding, entry.line));
+            }
+        }
+
+        keymap.check_conflicts(document, &rebound)?;
+        Ok(keymap)
+    }
+
+    /// what the key is bound to, if anything
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(binding, _)| binding.matches(key))
+            .map(|&(_, action)| action)
+    }
+
+    /// the names of the keys bound to the action
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|&&(_, bound)| bound == action)
+            .map(|(binding, _)| binding.name())
+            .collect()
+    }
+
+    /// a line per action with the keys that do it, for the help screen
+    pub fn help(&self) -> String {
+        let lines: Vec<String> = Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys = self.keys_for(action);
+                let keys = if keys.is_empty() {
+                    "(unbound)".to_string()
+                } else {
+                    keys.join(", ")
+                };
+                format!("{:<26}{}", action.description(), keys)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// refuse a key that's bound to two different actions
+    fn check_conflicts(
+        &self,
+        document: &Document,
+        rebound: &[(Binding, usize)],
+    ) -> Result<(), DataError> {
+        for (index, &(binding, action)) in self.bindings.iter().enumerate() {
+            let other = self.bindings[..index]
+                .iter()
+                .find(|&&(other, bound)| other == binding && bound != action);
+            if let Some(&(_, other)) = other {
+                let line = rebound
+                    .iter()
+                    .find(|&&(rebound, _)| rebound == binding)
+                    .map_or(0, |&(_, line)| line);
+                return Err(document.error(
+                    line,
+                    format!(
+                        "`{}` is bound to both {} and {}, rebind one of them",
+                        binding.name(),
+                        other.name(),
+                        action.name()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `action = "key"` or `action = ["key", ...]`; an empty array unbinds the action
+fn bindings_from(document: &Document, entry: &Entry) -> Result<Vec<Binding>, DataError> {
+    let names = match &entry.value {
+        Value::String(name) => vec![name],
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(name) => Ok(name),
+                other => Err(document.error(
+                    entry.line,
+                    format!("expected key names, found {}", other.kind()),
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        other => {
+            return Err(document.error(
+                entry.line,
+                format!(
+                    "`{}` expected a key name or an array of them, found {}",
+                    entry.key,
+                    other.kind()
+                ),
+            ))
+        }
+    };
+
+    names
+        .into_iter()
+        .map(|name| {
+            Binding::parse(name).ok_or_else(|| {
+                document.error(
+                    entry.line,
+                    format!(
+                        "unknown key '{}', expected a single character or one of {}, \
+                         optionally after Alt+ or Ctrl+",
+                        name,
+                        KEY_NAMES
+                            .iter()
+                            .map(|(name, _)| *name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+fn expected_actions() -> String {
+    let names: Vec<_> = Action::ALL.iter().map(|action| action.name()).collect();
+    format!("expected one of {}", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::scripted;
+    use tcod::input::Event;
+
+    fn read(text: &str) -> Result<Keymap, DataError> {
+        Keymap::read(&Document::parse("keymap.toml", text)?)
+    }
+
+    /// the line and message of the error the keymap gives
+    fn error(text: &str) -> (usize, String) {
+        let error = read(text).unwrap_err();
+        (error.line, error.message)
+    }
+
+    /// the text event of the character, with the modifiers held
+    fn typed(glyph: char, alt: bool, ctrl: bool) -> Key {
+        match scripted::text(glyph) {
+            Event::Key(mut key) => {
+                key.alt = alt;
+                key.ctrl = ctrl;
+                key
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn the_shipped_keymap_is_the_default_one() {
+        let keymap = Keymap::load(KEYMAP_PATH, true).unwrap();
+        assert_eq!(keymap.help(), Keymap::default().help());
+        assert_eq!(
+            keymap.action(typed('i', false, false)),
+            Some(Action::Inventory)
+        );
+        // a missing file is fine unless it was asked for
+        assert!(Keymap::load("no such keymap.toml", false).is_ok());
+        assert!(Keymap::load("no such keymap.toml", true).is_err());
+    }
+
+    #[test]
+    fn a_key_can_only_do_one_thing() {
+        assert_eq!(
+            error("look = \"i\""),
+            (
+                1,
+                "`i` is bound to both inventory and look, rebind one of them".into()
+            )
+        );
+        assert_eq!(
+            error("look = \"v\"\nhistory = [\"m\", \"v\"]"),
+            (
+                1,
+                "`v` is bound to both look and history, rebind one of them".into()
+            )
+        );
+        // once the other action lets go of the key it's free
+        let keymap =
+            read("inventory = \"u\"\nmove_north_east = \"NumPad9\"\nlook = \"i\"").unwrap();
+        assert_eq!(keymap.action(typed('i', false, false)), Some(Action::Look));
+        assert_eq!(
+            keymap.action(typed('u', false, false)),
+            Some(Action::Inventory)
+        );
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_refused() {
+        let (line, message) = error("look = \"x\"\njump = \"j\"");
+        assert_eq!(line, 2);
+        assert!(message.starts_with("unknown action `jump`, expected one of move_north,"));
+
+        let (line, message) = error("look = \"Look\"");
+        assert_eq!(line, 1);
+        assert!(message.starts_with("unknown key 'Look', expected a single character"));
+        assert!(error("look = \"Alt+\"").1.starts_with("unknown key 'Alt+'"));
+    }
+
+    #[test]
+    fn modifiers_have_to_be_held_and_shift_is_the_character() {
+        let keymap = read("look = \"Alt+.\"\nhistory = \"Ctrl+Alt+M\"").unwrap();
+        assert_eq!(keymap.action(typed('.', false, false)), Some(Action::Wait));
+        assert_eq!(keymap.action(typed('.', true, false)), Some(Action::Look));
+        assert_eq!(keymap.action(typed('M', true, true)), Some(Action::History));
+        assert_eq!(keymap.action(typed('M', true, false)), None);
+        assert_eq!(keymap.action(typed('m', true, true)), None);
+        assert_eq!(keymap.keys_for(Action::History), vec!["Ctrl+Alt+M"]);
+
+        // shifted keys are bound by the character they type, ">" rather than Shift+.
+        assert_eq!(
+            keymap.action(typed('>', false, false)),
+            Some(Action::Descend)
+        );
+        assert!(error("descend = \"Shift+.\"")
+            .1
+            .starts_with("unknown key 'Shift+.'"));
+    }
+}
@@ -0,0 +1,406 @@
+use crate::domain::{self, get_equipped_in_slot, Ai, Equipment, Game};
+use crate::keymap::Keymap;
+use crate::render::{numpad_digit, starts_text, wrap_text, Renderer};
+use crate::render_all;
+use crate::world::{Entity, World};
+use std::cmp::Ordering;
+use std::mem;
+use tcod::colors::*;
+use tcod::input::{Event, KeyCode};
+use tcod::TextAlignment;
+
+const PANEL_WIDTH: i32 = 34;
+
+/// Look at the things in sight one by one, closest first, with a panel describing the one
+/// under the cursor. Tab and the movement keys go to the next or previous one, pointing at
+/// one with the mouse picks it; Escape, a right-click or any other key stops looking.
+pub fn look_around(renderer: &mut impl Renderer, keymap: &Keymap, game: &mut Game, world: &World) {
+    let entities = visible_entities(game, world);
+    if entities.is_empty() {
+        game.messages
+            .add("There's nothing in sight to look at.", WHITE);
+        return;
+    }
+
+    let mut index = 0;
+    let mut numpad_pressed = false;
+    loop {
+        let entity = entities[index];
+        let (x, y) = world.pos(entity).unwrap_or_default();
+
+        // the game with the entity highlighted, and the panel on the other half of the map
+        render_all(renderer, (x, y), game, world, false);
+        renderer.blend_background(x, y, LIGHT_GREEN, 0.8);
+        let panel_x = if x < game.config.map_width / 2 {
+            game.config.map_width - PANEL_WIDTH - 1
+        } else {
+            1
+        };
+        let footer = format!(
+            "{} of {}, Tab: next, Escape: stop",
+            index + 1,
+            entities.len()
+        );
+        draw_panel(renderer, panel_x, &describe(entity, game, world), &footer);
+        renderer.flush();
+
+        match renderer.wait_for_event() {
+            Event::Key(key) => {
+                let direction = keymap.action(key).and_then(|action| action.direction());
+                // the digit a numpad key types doesn't stop looking
+                let after_numpad = mem::replace(&mut numpad_pressed, numpad_digit(key));
+                match (key.code, direction) {
+                    (KeyCode::Tab, _) => index = (index + 1) % entities.len(),
+                    // right and down go forwards, left and up back
+                    (_, Some((dx, dy))) if dx + dy > 0 => index = (index + 1) % entities.len(),
+                    (_, Some((dx, dy))) if dx + dy < 0 => {
+                        index = (index + entities.len() - 1) % entities.len()
+                    }
+                    (_, Some(_)) => {}
+                    (KeyCode::Text, None) if after_numpad => {}
+                    _ if starts_text(key) => {}
+                    _ => return,
+                }
+            }
+            Event::Mouse(mouse) => {
+                if mouse.rbutton_pressed {
+                    return;
+                }
+                let pointed = (mouse.cx as i32, mouse.cy as i32);
+                if let Some(pointed) = entities.iter().position(|&e| world.pos(e) == Some(pointed))
+                {
+                    index = pointed;
+                }
+            }
+        }
+    }
+}
+
+/// everything with a look the player can see right now, except themselves, closest first
+fn visible_entities(game: &Game, world: &World) -> Vec<Entity> {
+    let player = match world.positions.get(world.player) {
+        Some(player) => *player,
+        None => return vec![],
+    };
+    let mut entities: Vec<_> = world
+        .renderables
+        .iter()
+        .filter_map(|(entity, _)| Some((entity, *world.positions.get(entity)?)))
+        .filter(|&(entity, position)| {
+            entity != world.player && game.fov.is_in_fov(position.x, position.y)
+        })
+        .collect();
+
+    // monsters before the items they stand on
+    entities.sort_by(|&(a, a_position), &(b, b_position)| {
+        player
+            .distance_to(&a_position)
+            .total_cmp(&player.distance_to(&b_position))
+            .then_with(|| world.blocks.contains(b).cmp(&world.blocks.contains(a)))
+    });
+    entities.into_iter().map(|(entity, _)| entity).collect()
+}
+
+/// the lines of the panel about the entity, each with its color
+fn describe(entity: Entity, game: &Game, world: &World) -> Vec<(String, Color)> {
+    let name = world.name(entity);
+    let color = world.renderables.get(entity).map_or(WHITE, |r| r.color);
+    let mut lines = vec![(capitalize(&domain::item_label(entity, world)), color)];
+
+    if world.fighters.contains(entity) {
+        lines.extend(describe_monster(entity, world));
+    }
+    if let Some(&equipment) = world.equipment.get(entity) {
+        lines.extend(describe_equipment(entity, equipment, world));
+    }
+    if name == "stairs" {
+        let text = format!(
+            "They lead down to dungeon level {}.",
+            game.dungeon_level + 1
+        );
+        lines.push((text, LIGHT_GREY));
+    }
+    if let Some(description) = game.templates.description(name) {
+        lines.push((description.into(), LIGHT_GREY));
+    }
+
+    lines
+}
+
+/// how hurt the monster looks and how it measures up to the player
+fn describe_monster(monster: Entity, world: &World) -> Vec<(String, Color)> {
+    let player = world.player;
+    let mut lines = vec![];
+
+    let hp = world.fighters.get(monster).map_or(0, |f| f.hp);
+    let max_hp = domain::max_hp(monster, world);
+    lines.push((format!("It looks {}.", health_state(hp, max_hp)), LIGHT_RED));
+    if let Some(Ai::Confused { .. }) = world.ais.get(monster) {
+        lines.push(("It's stumbling about, confused.".into(), LIGHT_BLUE));
+    }
+
+    let (power, defense) = (
+        domain::power(monster, world),
+        domain::defense(monster, world),
+    );
+    let (your_power, your_defense) = (domain::power(player, world), domain::defense(player, world));
+    lines.push((
+        format!(
+            "Power {} (yours {}), defense {} (yours {})",
+            power, your_power, defense, your_defense
+        ),
+        WHITE,
+    ));
+
+    // the same formula `attack` uses
+    let its_damage = (power - your_defense).max(0);
+    let your_damage = (your_power - defense).max(0);
+    lines.push((
+        format!(
+            "It hits you for {}, you hit it for {}.",
+            its_damage, your_damage
+        ),
+        WHITE,
+    ));
+    if your_damage > 0 {
+        let hits = (hp + your_damage - 1) / your_damage;
+        let text = match hits {
+            1 => "One more hit will kill it.".into(),
+            _ => format!("It will take {} hits to kill it.", hits),
+        };
+        lines.push((text, WHITE));
+    } else {
+        lines.push(("You can't hurt it.".into(), WHITE));
+    }
+
+    let speed = |entity| world.actors.get(entity).map(|actor| actor.speed());
+    if let (Some(its_speed), Some(your_speed)) = (speed(monster), speed(player)) {
+        let text = match its_speed.cmp(&your_speed) {
+            Ordering::Greater => "It's faster than you.",
+            Ordering::Less => "It's slower than you.",
+            Ordering::Equal => "It's as fast as you.",
+        };
+        lines.push((text.into(), WHITE));
+    }
+
+    lines
+}
+
+/// where the equipment goes, what it gives and what it would replace
+fn describe_equipment(item: Entity, equipment: Equipment, world: &World) -> Vec<(String, Color)> {
+    let bonuses: Vec<_> = [
+        (equipment.power_bonus, "power"),
+        (equipment.defense_bonus, "defense"),
+        (equipment.max_hp_bonus, "max HP"),
+    ]
+    .iter()
+    .filter(|&&(bonus, _)| bonus != 0)
+    .map(|(bonus, stat)| format!("{:+} {}", bonus, stat))
+    .collect();
+
+    let mut text = format!("Worn on the {}", equipment.slot);
+    if !bonuses.is_empty() {
+        text = format!("{}: {}", text, bonuses.join(", "));
+    }
+    let mut lines = vec![(format!("{}.", text), WHITE)];
+
+    match get_equipped_in_slot(equipment.slot, world.player, world) {
+        Some(equipped) if equipped != item => lines.push((
+            format!("It would replace your {}.", world.name(equipped)),
+            WHITE,
+        )),
+        _ => {}
+    }
+
+    lines
+}
+
+/// how a monster with the given health looks to the player
+fn health_state(hp: i32, max_hp: i32) -> &'static str {
+    match hp * 100 / max_hp.max(1) {
+        100.. => "unhurt",
+        75..=99 => "lightly wounded",
+        50..=74 => "wounded",
+        25..=49 => "badly wounded",
+        _ => "nearly dead",
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// draw the lines in a box at the top of the map, followed by the footer
+fn draw_panel(renderer: &mut impl Renderer, x: i32, lines: &[(String, Color)], footer: &str) {
+    let text_width = PANEL_WIDTH - 2;
+    let mut wrapped = vec![];
+    for (index, (text, color)) in lines.iter().enumerate() {
+        // a blank line between the name and the rest
+        if index == 1 {
+            wrapped.push((String::new(), WHITE));
+        }
+        wrapped.extend(
+            wrap_text(text, text_width)
+                .into_iter()
+                .map(|line| (line, *color)),
+        );
+    }
+    wrapped.push((String::new(), WHITE));
+    wrapped.push((footer.into(), DARK_GREY));
+
+    let (y, height) = (1, wrapped.len() as i32 + 2);
+    for cell_y in y..y + height {
+        for cell_x in x..x + PANEL_WIDTH {
+            if renderer.in_bounds(cell_x, cell_y) {
+                renderer.put_char(cell_x, cell_y, ' ', WHITE);
+                renderer.blend_background(cell_x, cell_y, BLACK, 0.7);
+            }
+        }
+    }
+    for (offset, (line, color)) in wrapped.iter().enumerate() {
+        let line_y = y + 1 + offset as i32;
+        renderer.print(x + 1, line_y, line, *color, TextAlignment::Left);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, CONFIG_PATH};
+    use crate::domain::{spawn_at, DeathCallback, Fighter};
+    use crate::new_game;
+    use crate::render::{scripted, GridRenderer};
+    use crate::templates::Templates;
+
+    /// a new game with the FOV worked out and nothing but the player in it
+    fn alone() -> (Game, World) {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        let templates = Templates::load(&config.templates_path).unwrap();
+        let (mut game, mut world) = new_game(42, &config, &templates);
+        let mut grid = GridRenderer::new(config.screen_width, config.screen_height);
+        render_all(&mut grid, (-1, -1), &mut game, &world, true);
+        for entity in world.entities() {
+            if entity != world.player {
+                world.despawn(entity);
+            }
+        }
+        (game, world)
+    }
+
+    /// the floor tiles in sight other than the player's, closest first
+    fn tiles_in_sight(game: &Game, world: &World) -> Vec<(i32, i32)> {
+        let player = *world.positions.get(world.player).unwrap();
+        let mut tiles = vec![];
+        for x in 0..game.config.map_width {
+            for y in 0..game.config.map_height {
+                if game.fov.is_in_fov(x, y)
+                    && !game.map[x as usize][y as usize].blocked
+                    && (x, y) != (player.x, player.y)
+                {
+                    tiles.push((x, y));
+                }
+            }
+        }
+        tiles.sort_by(|&(ax, ay), &(bx, by)| {
+            player.distance(ax, ay).total_cmp(&player.distance(bx, by))
+        });
+        tiles
+    }
+
+    fn monster(world: &mut World, (x, y): (i32, i32), hp: i32, defense: i32) -> Entity {
+        let monster = spawn_at(world, x, y, 'o', "orc", GREEN, true);
+        let fighter = Fighter::new(defense, hp, 10, 3, 35, DeathCallback::Monster);
+        world.fighters.insert(monster, fighter);
+        monster
+    }
+
+    fn item(world: &mut World, (x, y): (i32, i32)) -> Entity {
+        spawn_at(world, x, y, '!', "healing potion", VIOLET, false)
+    }
+
+    #[test]
+    fn the_closest_come_first_and_monsters_before_their_items() {
+        let (game, mut world) = alone();
+        let tiles = tiles_in_sight(&game, &world);
+        let (near, far) = (tiles[0], tiles[tiles.len() - 1]);
+
+        // spawned out of order, the item on the far tile before the monster standing on it
+        let far_item = item(&mut world, far);
+        let far_monster = monster(&mut world, far, 10, 0);
+        let near_item = item(&mut world, near);
+        assert_eq!(
+            visible_entities(&game, &world),
+            vec![near_item, far_monster, far_item]
+        );
+    }
+
+    #[test]
+    fn tab_and_the_movement_keys_cycle_both_ways() {
+        let (mut game, mut world) = alone();
+        let tiles = tiles_in_sight(&game, &world);
+        for &tile in &tiles[..3] {
+            item(&mut world, tile);
+        }
+
+        let mut grid = GridRenderer::new(game.config.screen_width, game.config.screen_height);
+        // the numpad keys followed by their digit, as with Num Lock on
+        grid.events.extend(vec![
+            scripted::key(KeyCode::Tab),
+            scripted::key(KeyCode::Char),
+            scripted::text('j'),
+            scripted::key(KeyCode::NumPad6),
+            scripted::text('6'),
+            scripted::key(KeyCode::Up),
+            scripted::key(KeyCode::NumPad8),
+            scripted::text('8'),
+        ]);
+        look_around(&mut grid, &Keymap::default(), &mut game, &world);
+
+        // 2, 3, wrapping around to 1, back to 3 and then 2, where the input ran out
+        assert!(grid.events.is_empty());
+        assert!(grid.snapshot().contains("2 of 3, Tab: next, Escape: stop"));
+    }
+
+    #[test]
+    fn monsters_are_sized_up_against_the_player() {
+        assert_eq!(health_state(10, 10), "unhurt");
+        assert_eq!(health_state(8, 10), "lightly wounded");
+        assert_eq!(health_state(5, 10), "wounded");
+        assert_eq!(health_state(3, 10), "badly wounded");
+        assert_eq!(health_state(1, 10), "nearly dead");
+
+        let (game, mut world) = alone();
+        let tile = tiles_in_sight(&game, &world)[0];
+        // whatever the player wears, 4 power in all
+        let player = world.player;
+        let bonus = domain::power(player, &world) - world.fighters.get(player).unwrap().base_power;
+        world.fighters.get_mut(player).unwrap().base_power = 4 - bonus;
+        let lines = |monster, world: &World| -> Vec<String> {
+            describe_monster(monster, world)
+                .into_iter()
+                .map(|(text, _)| text)
+                .collect()
+        };
+
+        let orc = monster(&mut world, tile, 10, 0);
+        let described = lines(orc, &world);
+        assert_eq!(described[0], "It looks unhurt.");
+        assert!(described.contains(&"It will take 3 hits to kill it.".to_string()));
+
+        world.fighters.get_mut(orc).unwrap().hp = 3;
+        let described = lines(orc, &world);
+        assert_eq!(described[0], "It looks badly wounded.");
+        assert!(described.contains(&"One more hit will kill it.".to_string()));
+
+        world.fighters.get_mut(orc).unwrap().base_defense = 4;
+        let described = lines(orc, &world);
+        assert!(described
+            .iter()
+            .any(|line| line.ends_with("you hit it for 0.")));
+        assert!(described.contains(&"You can't hurt it.".to_string()));
+    }
+}
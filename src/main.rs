@@ -1,269 +1,606 @@
 #![allow(clippy::ptr_arg)]
 
+mod config;
+mod dijkstra;
 mod domain;
+mod history;
+mod keymap;
+mod look;
+mod mapgen;
 mod menu;
-
+mod pathfinding;
+mod render;
+mod save;
+mod scheduler;
+mod targeting;
+mod templates;
+mod toml;
+mod world;
+
+use crate::config::{Config, CONFIG_PATH};
+use crate::dijkstra::DijkstraMap;
 use crate::domain::{is_blocked, Messages, UseResult};
-use crate::menu::inventory_menu;
-use domain::{Ai, DeathCallback, Fighter, Game, Item, Map, Object, PlayerAction, Rect, Tile};
-use rand::Rng;
+use crate::keymap::{Action, Keymap, KEYMAP_PATH};
+use crate::mapgen::{
+    Bsp, Caves, DrunkardsWalk, Generator, Layout, MapGenerator, Mixed, RandomRooms,
+};
+use crate::menu::{inventory_menu, menu, msgbox};
+use crate::pathfinding::Path;
+use crate::render::{GridRenderer, Renderer};
+use crate::scheduler::{Actor, Boost, ATTACK_COST, NORMAL_SPEED, USE_ITEM_COST, WAIT_COST};
+use crate::targeting::{choose_target, Target, TargetKind};
+use crate::templates::Templates;
+use crate::world::{Entity, World};
+use domain::{
+    from_dungeon_level, get_equipped_in_slot, spawn_at, Ai, DeathCallback, Fighter, Game,
+    Inventory, Item, Map, PlayerAction, Transition,
+};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::cmp;
 use tcod::colors::*;
 use tcod::console::*;
 use tcod::input::{self, Event, Key, Mouse};
-use tcod::map::{FovAlgorithm, Map as FovMap};
-
-// actual size of the window
-const SCREEN_WIDTH: i32 = 80;
-const SCREEN_HEIGHT: i32 = 50;
-
-// size of the map
-const MAP_WIDTH: i32 = 80;
-const MAP_HEIGHT: i32 = 43;
-
-// parameters for dungeon generator
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
-
-// tile colors
-const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-const COLOR_LIGHT_WALL: Color = Color {
-    r: 130,
-    g: 110,
-    b: 50,
-};
-const COLOR_DARK_GROUND: Color = Color {
-    r: 50,
-    g: 50,
-    b: 150,
-};
-const COLOR_LIGHT_GROUND: Color = Color {
-    r: 200,
-    g: 180,
-    b: 50,
-};
-
-const FONT_PATH: &str = "src/assets/arial_10x10.png";
-const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
-
-// fov constants
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic; // default FOV algorithm
-const FOV_LIGHT_WALLS: bool = true; // light walls or not
-const TORCH_RADIUS: i32 = 10;
-
-// panel constants
-const BAR_WIDTH: i32 = 20;
-const PANEL_HEIGHT: i32 = 7;
-const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
-const MSG_X: i32 = BAR_WIDTH + 2;
-const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
-const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
-
-const HEAL_AMOUNT: i32 = 4;
-const INVENTORY_WIDTH: i32 = 50;
-const MAX_INVENTORY: usize = 26; // tied to 26 letters in alphabet
-const MAX_ROOM_ITEMS: i32 = 3;
-const MAX_ROOM_MONSTERS: i32 = 3;
-const PLAYER: usize = 0; // player is always the first object
+use tcod::map::Map as FovMap;
+
+const LIGHTNING_DAMAGE: i32 = 40;
+const LIGHTNING_RANGE: i32 = 5;
+const CONFUSE_RANGE: i32 = 8;
+const CONFUSE_NUM_TURNS: i32 = 10;
+const FIREBALL_RADIUS: i32 = 3;
+const FIREBALL_DAMAGE: i32 = 25;
+const SPEED_POTION_TURNS: i32 = 20;
+const THROW_RANGE: i32 = 8;
+// how far monsters that carry things notice items lying around
+const ITEM_NOTICE_RANGE: i32 = 6;
+
+// experience and level-ups
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+const LEVEL_SCREEN_WIDTH: i32 = 40;
+const HELP_SCREEN_WIDTH: i32 = 60;
 
 // encapsulate all of our libtcod-related values
 struct Tcod {
     root: Root,
-    con: Offscreen,   // where the main game is rendered
-    panel: Offscreen, // TODO
-    fov: FovMap,
     key: Key,
     mouse: Mouse,
+    keymap: Keymap,
 }
 
-fn make_map(objects: &mut Vec<Object>) -> Map {
-    // fill map with "blocked" tiles
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+impl Renderer for Tcod {
+    fn size(&self) -> (i32, i32) {
+        (self.root.width(), self.root.height())
+    }
 
-    let mut rooms = vec![];
+    fn clear(&mut self) {
+        self.root.set_default_background(BLACK);
+        self.root.set_default_foreground(WHITE);
+        self.root.clear();
+    }
 
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+    fn background(&self, x: i32, y: i32) -> Color {
+        self.root.get_char_background(x, y)
+    }
 
-        // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
+    fn set_background(&mut self, x: i32, y: i32, color: Color) {
+        self.root
+            .set_char_background(x, y, color, BackgroundFlag::Set);
+    }
 
-        let new_room = Rect::new(x, y, w, h);
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, color: Color) {
+        self.root.set_char(x, y, glyph);
+        self.root.set_char_foreground(x, y, color);
+    }
 
-        // run through the other rooms and see if they intersect with this one
-        let has_intersections = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
+    fn flush(&mut self) {
+        self.root.flush();
+    }
 
-        if !has_intersections {
-            // paint it to the map's tiles
-            create_room(new_room, &mut map);
+    fn window_closed(&self) -> bool {
+        self.root.window_closed()
+    }
+
+    fn wait_for_event(&mut self) -> Event {
+        loop {
+            if let Some((_, event)) = input::check_for_event(input::KEY_PRESS | input::MOUSE) {
+                return event;
+            }
+            if self.root.window_closed() {
+                // reads as a key nothing understands, which backs out of anything
+                return Event::Key(Key::default());
+            }
+            // keep presenting the frame, which also keeps to the frame rate instead of spinning
+            self.root.flush();
+        }
+    }
+}
 
-            // add some objects to this room, such as monsters
-            place_objects(new_room, &map, objects);
+fn make_map(
+    world: &mut World,
+    level: u32,
+    config: &Config,
+    templates: &Templates,
+    rng: &mut StdRng,
+) -> Map {
+    let generator: &dyn MapGenerator = match config.generator {
+        Generator::Rooms => &RandomRooms,
+        Generator::Bsp => &Bsp,
+        Generator::Caves => &Caves,
+        Generator::Drunkard => &DrunkardsWalk,
+        Generator::Mixed => &Mixed,
+    };
+    let Layout {
+        map,
+        start,
+        stairs,
+        regions,
+    } = generator.generate(config, rng);
+
+    world.set_pos(world.player, start.0, start.1);
+
+    // add some entities to every region, such as monsters
+    for region in &regions {
+        place_objects(region, &map, world, level, templates, rng);
+    }
 
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
+    let stairs = spawn_at(world, stairs.0, stairs.1, '>', "stairs", WHITE, false);
+    if let Some(renderable) = world.renderables.get_mut(stairs) {
+        renderable.always_visible = true;
+    }
 
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                objects[PLAYER].set_pos(new_x, new_y);
-            } else {
-                // all rooms after the first: connect to previous with a tunnel
-
-                // center coordinates of previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                // flip a coin
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_horizontal_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_vertical_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move vertically, then horizontally
-                    create_vertical_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_horizontal_tunnel(prev_x, new_x, new_y, &mut map);
-                }
+    map
+}
+
+/// take one step along the entity's cached path to the target, finding a new path first if
+/// the target moved, the entity strayed from the path or the next step is blocked
+fn move_towards(entity: Entity, target_x: i32, target_y: i32, map: &Map, world: &mut World) {
+    let (x, y) = match world.pos(entity) {
+        Some(pos) => pos,
+        None => return,
+    };
+    let target = (target_x, target_y);
+
+    let is_usable = |path: &Path| {
+        path.target == target
+            && path.next_step().is_some_and(|(step_x, step_y)| {
+                (step_x - x).abs() <= 1
+                    && (step_y - y).abs() <= 1
+                    && !is_blocked(step_x, step_y, map, world)
+            })
+    };
+    if !world.paths.get(entity).is_some_and(is_usable) {
+        match pathfinding::find_path((x, y), target, map, world) {
+            Some(path) => world.paths.insert(entity, path),
+            // there's no way there at all, wait for one to open
+            None => {
+                world.paths.remove(entity);
+                return;
             }
+        }
+    }
 
-            // append the new room to the list
-            // TODO: COPY
-            rooms.push(new_room);
+    if let Some(path) = world.paths.get_mut(entity) {
+        if let Some((step_x, step_y)) = path.next_step() {
+            path.advance();
+            // even the best path can lead through someone, then wait for them to move on
+            domain::move_by(entity, step_x - x, step_y - y, map, world);
         }
     }
+}
 
-    map
+fn ai_take_turn(monster: Entity, game: &mut Game, world: &mut World) {
+    use Ai::*;
+    let energy = world.actors.get(monster).map(|actor| actor.energy);
+
+    // take the AI out while it acts, it may replace itself (eg. when confusion wears off)
+    if let Some(ai) = world.ais.remove(monster) {
+        let new_ai = match ai {
+            Confused {
+                previous_ai,
+                num_turns,
+            } => ai_confused(monster, game, world, previous_ai, num_turns),
+            // monsters that carry things see to them before anything else
+            ai if ai_handle_items(monster, game, world) => ai,
+            Basic => ai_basic(monster, game, world),
+            Fleeing { threshold } => ai_fleeing(monster, game, world, threshold),
+            Ranged { range } => ai_ranged(monster, game, world, range),
+            Wanderer => ai_wanderer(monster, game, world),
+        };
+        // only put it back if the monster is still around to use it
+        if world.fighters.contains(monster) {
+            world.ais.insert(monster, new_ai);
+        }
+    }
+
+    // a monster that found nothing to do waits, rather than holding up everyone else
+    if world.actors.get(monster).map(|actor| actor.energy) == energy {
+        scheduler::spend(monster, WAIT_COST, world);
+    }
 }
 
-fn create_room(room: Rect, map: &mut Map) {
-    // go through the tiles in the rectangle and make them passable
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+/// let a monster that carries things drink, throw or pick one up. Returns whether it did,
+/// which takes its turn.
+fn ai_handle_items(monster: Entity, game: &mut Game, world: &mut World) -> bool {
+    let player = world.player;
+    let (x, y) = match world.pos(monster) {
+        Some(pos) if world.inventories.contains(monster) => pos,
+        _ => return false,
+    };
+    let player_pos = world.positions.get(player).copied();
+    let seen = game.fov.is_in_fov(x, y);
+    let carried = |effect: Item, world: &World| {
+        world
+            .inventory(monster)
+            .iter()
+            .copied()
+            .find(|&item| world.items.get(item) == Some(&effect))
+    };
+
+    // drink a healing potion when badly hurt
+    let hurt = world
+        .fighters
+        .get(monster)
+        .is_some_and(|f| f.hp * 2 < domain::max_hp(monster, world));
+    if let Some(potion) = carried(Item::Heal, world).filter(|_| hurt) {
+        monster_drink(monster, potion, game, world);
+        return true;
+    }
+
+    if let Some(player_pos) = player_pos.filter(|_| seen && player_alive(world)) {
+        // speed up for the fight
+        let boost = |entity| world.actors.get(entity).and_then(|actor| actor.boost);
+        if let Some(potion) = carried(Item::Haste, world).filter(|_| boost(monster).is_none()) {
+            monster_drink(monster, potion, game, world);
+            return true;
+        }
+
+        // slow the player down while they're still out of reach
+        let distance = player_pos.distance(x, y);
+        let in_range = distance >= 2.0 && distance <= THROW_RANGE as f32;
+        let slowed = matches!(boost(player), Some((Boost::Slow, _)));
+        let clear_shot =
+            targeting::flight_path((x, y), (player_pos.x, player_pos.y), &game.map, world).last()
+                == Some(&(player_pos.x, player_pos.y));
+        if let Some(potion) =
+            carried(Item::Slow, world).filter(|_| in_range && !slowed && clear_shot)
+        {
+            game.messages.add(
+                format!(
+                    "The {} throws a {} at you!",
+                    world.name(monster),
+                    world.name(potion)
+                ),
+                LIGHT_CYAN,
+            );
+            domain::use_up(monster, potion, world);
+            scheduler::spend(monster, ATTACK_COST, world);
+            splash(Item::Slow, player, game, world);
+            return true;
+        }
+    }
+
+    // pick up whatever lies where it stands, unless the player is right there
+    let busy = player_pos.is_some_and(|pos| pos.distance(x, y) < 2.0);
+    let here = world
+        .items
+        .iter()
+        .map(|(item, _)| item)
+        .find(|&item| world.pos(item) == Some((x, y)));
+    if let Some(item) = here.filter(|_| !busy) {
+        if seen {
+            game.messages.add(
+                format!(
+                    "The {} picks up {}.",
+                    world.name(monster),
+                    domain::item_amount(item, world)
+                ),
+                LIGHT_GREY,
+            );
+        }
+        world.positions.remove(item);
+        domain::put_in_inventory(monster, item, world);
+        domain::wear_if_free(monster, item, world);
+        scheduler::spend(monster, USE_ITEM_COST, world);
+        return true;
+    }
+
+    // go for the items nearby while the player isn't around
+    if !seen {
+        let nearby: Vec<_> = world
+            .items
+            .iter()
+            .filter_map(|(item, _)| world.positions.get(item))
+            .filter(|item| item.distance(x, y) <= ITEM_NOTICE_RANGE as f32)
+            .map(|item| (item.x, item.y))
+            .collect();
+        if !nearby.is_empty() {
+            let toward_items = DijkstraMap::new(&game.map, &nearby);
+            return roll_downhill(monster, &toward_items, &game.map, world);
         }
     }
+
+    false
+}
+
+/// the monster drinks one of the potions it carries
+fn monster_drink(monster: Entity, potion: Entity, game: &mut Game, world: &mut World) {
+    let effect = match world.items.get(potion) {
+        Some(&effect) => effect,
+        None => return,
+    };
+    if world
+        .pos(monster)
+        .is_some_and(|(x, y)| game.fov.is_in_fov(x, y))
+    {
+        game.messages.add(
+            format!(
+                "The {} drinks a {}.",
+                world.name(monster),
+                world.name(potion)
+            ),
+            LIGHT_GREY,
+        );
+    }
+    domain::use_up(monster, potion, world);
+    scheduler::spend(monster, USE_ITEM_COST, world);
+    splash(effect, monster, game, world);
 }
 
-fn create_horizontal_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
-        map[x as usize][y as usize] = Tile::empty();
+fn ai_basic(monster: Entity, game: &mut Game, world: &mut World) -> Ai {
+    chase(monster, game, world);
+    Ai::Basic
+}
+
+/// move towards the player and attack when next to them - if you can see it, it can see you.
+/// Returns whether the monster saw the player.
+fn chase(monster: Entity, game: &mut Game, world: &mut World) -> bool {
+    let player = world.player;
+    let (monster_pos, player_pos) =
+        match (world.positions.get(monster), world.positions.get(player)) {
+            (Some(&monster_pos), Some(&player_pos)) => (monster_pos, player_pos),
+            _ => return false,
+        };
+
+    if !game.fov.is_in_fov(monster_pos.x, monster_pos.y) {
+        return false;
+    }
+
+    if monster_pos.distance_to(&player_pos) >= 2.0 {
+        // move towards player if too far away
+        move_towards(monster, player_pos.x, player_pos.y, &game.map, world);
+    } else if player_alive(world) {
+        // close enough, player is still slive - attack
+        domain::attack(monster, player, game, world);
     }
+    true
 }
 
-fn create_vertical_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
-        map[x as usize][y as usize] = Tile::empty();
+/// take the step `dijkstra` leads to, returns false if there's nowhere better to go
+fn roll_downhill(monster: Entity, dijkstra: &DijkstraMap, map: &Map, world: &mut World) -> bool {
+    let (x, y) = match world.pos(monster) {
+        Some(pos) => pos,
+        None => return false,
+    };
+    match dijkstra.downhill((x, y), map, world) {
+        Some((next_x, next_y)) => {
+            domain::move_by(monster, next_x - x, next_y - y, map, world);
+            true
+        }
+        None => false,
     }
 }
 
-fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
-    // vector from this object to the target, and distance
-    let dx = target_x - objects[id].x;
-    let dy = target_y - objects[id].y;
-    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+fn ai_fleeing(monster: Entity, game: &mut Game, world: &mut World, threshold: i32) -> Ai {
+    let hurt = world
+        .fighters
+        .get(monster)
+        .is_some_and(|f| f.hp * 100 < domain::max_hp(monster, world) * threshold);
+    let seen = world
+        .pos(monster)
+        .is_some_and(|(x, y)| game.fov.is_in_fov(x, y));
+
+    match world.pos(world.player) {
+        Some(player_pos) if hurt && seen => {
+            // run away from the player, cornered monsters fight back
+            let flee = DijkstraMap::new(&game.map, &[player_pos]).invert(&game.map);
+            if !roll_downhill(monster, &flee, &game.map, world) {
+                chase(monster, game, world);
+            }
+        }
+        _ => {
+            chase(monster, game, world);
+        }
+    }
 
-    // normalize it to length 1 (preserving direction), then round it and convert to integer
-    // so the movement is restricted to the map grid
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
-    domain::move_by(id, dx, dy, map, objects);
+    Ai::Fleeing { threshold }
 }
 
-fn ai_take_turn(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
-    // a basic monster takes its turn - if you can see it, it can see you
-    let (monster_x, monster_y) = objects[monster_id].pos();
+fn ai_ranged(monster: Entity, game: &mut Game, world: &mut World, range: i32) -> Ai {
+    let player = world.player;
+    let (monster_pos, player_pos) = match (world.pos(monster), world.pos(player)) {
+        (Some(monster_pos), Some(player_pos)) => (monster_pos, player_pos),
+        _ => return Ai::Ranged { range },
+    };
 
-    if tcod.fov.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            // move towards player if too far away
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            // close enough, player is still slive - attack
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, game);
+    if game.fov.is_in_fov(monster_pos.0, monster_pos.1) {
+        let toward_player = DijkstraMap::new(&game.map, &[player_pos]);
+        let steps = toward_player.value(monster_pos.0, monster_pos.1);
+
+        if steps > 1.0 && steps <= range as f32 && player_alive(world) {
+            // in range but not in reach: shoot
+            domain::attack(monster, player, game, world);
+        } else {
+            // get back to shooting distance, preferring to back away over going around
+            let shooting_spots = toward_player.tiles_at(range as f32);
+            let keep_distance = DijkstraMap::new(&game.map, &shooting_spots)
+                .combine(&toward_player.invert(&game.map), 0.1);
+            if !roll_downhill(monster, &keep_distance, &game.map, world) && steps <= 1.0 {
+                chase(monster, game, world);
+            }
         }
     }
+
+    Ai::Ranged { range }
 }
 
-/// Mutably borrow two *separate* elements from the given slice.
-/// Panics when the indexes are equal or out of bounds.
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert_ne!(first_index, second_index);
+fn ai_wanderer(monster: Entity, game: &mut Game, world: &mut World) -> Ai {
+    if !chase(monster, game, world) {
+        // head for the closest tile the player hasn't explored yet
+        let unexplored: Vec<_> = (0..game.config.map_width)
+            .flat_map(|x| (0..game.config.map_height).map(move |y| (x, y)))
+            .filter(|&(x, y)| {
+                let tile = &game.map[x as usize][y as usize];
+                !tile.blocked && !tile.explored
+            })
+            .collect();
+        let toward_unexplored = DijkstraMap::new(&game.map, &unexplored);
+
+        if !roll_downhill(monster, &toward_unexplored, &game.map, world) {
+            // already there, or everything's explored: mill about
+            let dx = game.rng.gen_range(-1, 2);
+            let dy = game.rng.gen_range(-1, 2);
+            domain::move_by(monster, dx, dy, &game.map, world);
+        }
+    }
 
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+    Ai::Wanderer
+}
 
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
+fn ai_confused(
+    monster: Entity,
+    game: &mut Game,
+    world: &mut World,
+    previous_ai: Box<Ai>,
+    num_turns: i32,
+) -> Ai {
+    if num_turns >= 0 {
+        // still confused: move in a random direction, and decrease the number of turns confused
+        let dx = game.rng.gen_range(-1, 2);
+        let dy = game.rng.gen_range(-1, 2);
+        domain::move_by(monster, dx, dy, &game.map, world);
+
+        Ai::Confused {
+            previous_ai,
+            num_turns: num_turns - 1,
+        }
     } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
+        // restore the previous AI (this one will be deleted)
+        game.messages.add(
+            format!("The {} is no longer confused!", world.name(monster)),
+            RED,
+        );
+        *previous_ai
     }
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>) {
+fn place_objects(
+    region: &[(i32, i32)],
+    map: &Map,
+    world: &mut World,
+    level: u32,
+    templates: &Templates,
+    rng: &mut StdRng,
+) {
+    if region.is_empty() {
+        return;
+    }
+
+    // maximum number of monsters per region
+    let max_monsters = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 2 },
+            Transition { level: 4, value: 3 },
+            Transition { level: 6, value: 5 },
+        ],
+        level,
+    );
+
+    // chance of each monster, the templates guarantee one of them can spawn on every level
+    let monster_chances = templates
+        .monsters
+        .iter()
+        .map(|monster| from_dungeon_level(&monster.spawn, level));
+    let monster_choice = WeightedIndex::new(monster_chances).unwrap();
+
     // choose random number of monsters
-    let num_monsters = rand::thread_rng().gen_range(0, MAX_ROOM_MONSTERS + 1);
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
 
     for _ in 0..num_monsters {
-        // choose random coordinates for this monster
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        // choose a random tile for this monster
+        let (x, y) = region[rng.gen_range(0, region.len())];
 
         // only place it if tile is not blocked
-        if !domain::is_blocked(x, y, map, objects) {
-            let mut monster = if rand::random::<f32>() < 0.8 {
-                let mut orc = Object::new(x, y, 'o', "orc", DESATURATED_GREEN, true);
-                orc.fighter = Some(Fighter::new(0, 10, 10, 3, DeathCallback::Monster));
-                orc.ai = Some(Ai::Basic);
-                orc
-            } else {
-                let mut troll = Object::new(x, y, 'T', "troll", DARKER_GREEN, true);
-                troll.fighter = Some(Fighter::new(1, 16, 16, 4, DeathCallback::Monster));
-                troll.ai = Some(Ai::Basic);
-                troll
-            };
-
-            monster.alive = true;
-            objects.push(monster);
+        if !is_blocked(x, y, map, world) {
+            templates.monsters[monster_choice.sample(rng)].spawn(
+                world,
+                x,
+                y,
+                &templates.items,
+                rng,
+            );
         }
     }
 
+    // maximum number of items per region
+    let max_items = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 1 },
+            Transition { level: 4, value: 2 },
+        ],
+        level,
+    );
+
+    // chance of each item
+    let item_chances = templates
+        .items
+        .iter()
+        .map(|item| from_dungeon_level(&item.spawn, level));
+    let item_choice = WeightedIndex::new(item_chances).unwrap();
+
     // choose random number of items
-    let num_items = rand::thread_rng().gen_range(0, MAX_ROOM_ITEMS + 1);
+    let num_items = rng.gen_range(0, max_items + 1);
 
     for _ in 0..num_items {
         // choose random spot for this item
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        let (x, y) = region[rng.gen_range(0, region.len())];
 
         // only place it if the the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            // create a healing potion
-            let mut object = Object::new(x, y, '!', "healing potion", VIOLET, false);
-            object.item = Some(Item::Heal);
-            objects.push(object);
+        if !is_blocked(x, y, map, world) {
+            templates.items[item_choice.sample(rng)].spawn(world, x, y);
         }
     }
 }
 
-fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], should_compute_fov: bool) {
-    if should_compute_fov {
+fn render_all(
+    renderer: &mut impl Renderer,
+    look_at: (i32, i32),
+    game: &mut Game,
+    world: &World,
+    should_compute_fov: bool,
+) {
+    let player = world.player;
+    if let Some((x, y)) = world.pos(player).filter(|_| should_compute_fov) {
         // compute FOV if needed (ie. player moved or other)
-        let player = &objects[PLAYER];
-        tcod.fov
-            .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        game.fov.compute_fov(
+            x,
+            y,
+            game.config.torch_radius,
+            game.config.fov_light_walls,
+            game.config.fov_algorithm,
+        );
     }
+    let config = &game.config;
+
+    // start from a blank screen every frame
+    renderer.clear();
 
     // go through all tiles and set their background color
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
+    for y in 0..config.map_height {
+        for x in 0..config.map_width {
             let explored = &mut game.map[x as usize][y as usize].explored;
-            let visible = tcod.fov.is_in_fov(x, y);
+            let visible = game.fov.is_in_fov(x, y);
 
             if visible {
                 // since it's visible, it has been explored
@@ -274,70 +611,66 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], should_compu
                 let wall = game.map[x as usize][y as usize].block_sight;
                 let color = match (visible, wall) {
                     // outside FOV
-                    (false, true) => COLOR_DARK_WALL,
-                    (false, false) => COLOR_DARK_GROUND,
+                    (false, true) => config.color_dark_wall,
+                    (false, false) => config.color_dark_ground,
                     // inside FOV
-                    (true, true) => COLOR_LIGHT_WALL,
-                    (true, false) => COLOR_LIGHT_GROUND,
+                    (true, true) => config.color_light_wall,
+                    (true, false) => config.color_light_ground,
                 };
 
-                tcod.con
-                    .set_char_background(x, y, color, BackgroundFlag::Set)
+                renderer.set_background(x, y, color);
             }
         }
     }
 
-    let mut to_draw: Vec<_> = objects
+    // draw every entity that has a position and a look, if the player can see it
+    let mut to_draw: Vec<_> = world
+        .renderables
         .iter()
-        .filter(|o| tcod.fov.is_in_fov(o.x, o.y))
+        .filter_map(|(entity, renderable)| Some((entity, world.positions.get(entity)?, renderable)))
+        .filter(|(_, position, renderable)| {
+            game.fov.is_in_fov(position.x, position.y)
+                || (renderable.always_visible
+                    && game.map[position.x as usize][position.y as usize].explored)
+        })
         .collect();
 
-    // sort so that non-blocking objects are drawn first
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    // sort so that non-blocking entities are drawn first
+    to_draw.sort_by_key(|&(entity, _, _)| world.blocks.contains(entity));
 
-    // draw all objects in the list
-    for object in &to_draw {
-        if tcod.fov.is_in_fov(object.x, object.y) {
-            object.draw(&mut tcod.con);
-        }
+    for (_, position, renderable) in &to_draw {
+        renderer.put_char(position.x, position.y, renderable.glyph, renderable.color);
     }
 
-    // blit the console to root
-    blit(
-        &tcod.con,
-        (0, 0),
-        (MAP_WIDTH, MAP_HEIGHT),
-        &mut tcod.root,
-        (0, 0),
-        1.0,
-        1.0,
-    );
-
-    // prep the GUI panel
-    tcod.panel.set_default_background(BLACK);
-    tcod.panel.clear();
-
     // print the game messages, one line at a time, with wrapping
-    let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in game.messages.iter().rev() {
-        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+    let panel_y = config.panel_y();
+    let mut y = config.msg_height();
+    for message in game.messages.iter().rev() {
+        let msg = message.display();
+        let msg_height = render::wrap_text(&msg, config.msg_width()).len() as i32;
         y -= msg_height;
         if y < 0 {
             break;
         }
-        tcod.panel.set_default_foreground(color);
-        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        renderer.print_rect(
+            config.msg_x(),
+            panel_y + y,
+            config.msg_width(),
+            &msg,
+            message.color,
+        );
     }
 
     // show the player's stats
-    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
-    let max_hp = objects[PLAYER].fighter.map_or(0, |f| f.max_hp);
+    let fighter = world.fighters.get(player);
+    let hp = fighter.map_or(0, |f| f.hp);
+    let max_hp = domain::max_hp(player, world);
 
     render_bar(
-        &mut tcod.panel,
+        renderer,
         1,
-        1,
-        BAR_WIDTH,
+        panel_y + 1,
+        config.bar_width,
         "HP",
         hp,
         max_hp,
@@ -345,190 +678,728 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], should_compu
         DARKER_RED,
     );
 
-    // display names of objects under the mouse
-    tcod.panel.set_default_foreground(LIGHT_GREY);
-    tcod.panel.print_ex(
+    let xp = fighter.map_or(0, |f| f.xp);
+    let level = fighter.map_or(1, |f| f.level);
+
+    render_bar(
+        renderer,
+        1,
+        panel_y + 2,
+        config.bar_width,
+        "XP",
+        xp,
+        level_up_xp(level),
+        LIGHT_BLUE,
+        DARKER_BLUE,
+    );
+
+    renderer.print(
+        1,
+        panel_y + 3,
+        &format!("Dungeon level: {}", game.dungeon_level),
+        LIGHT_GREY,
+        TextAlignment::Left,
+    );
+    renderer.print(
+        1,
+        panel_y + 4,
+        &format!("Character level: {}", level),
+        LIGHT_GREY,
+        TextAlignment::Left,
+    );
+
+    // show the seed so a reported dungeon can be regenerated
+    renderer.print(
         1,
-        0,
-        BackgroundFlag::None,
+        panel_y + config.panel_height - 1,
+        &format!("Seed: {}", game.seed),
+        DARK_GREY,
         TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, objects, &tcod.fov),
     );
 
-    // blit the panel to root
-    blit(
-        &tcod.panel,
-        (0, 0),
-        (SCREEN_WIDTH, SCREEN_HEIGHT),
-        &mut tcod.root,
-        (0, PANEL_Y),
-        1.0,
-        1.0,
+    // display names of entities under the mouse (or the targeting cursor)
+    renderer.print(
+        1,
+        panel_y,
+        &get_names_at(look_at, world, &game.fov),
+        LIGHT_GREY,
+        TextAlignment::Left,
     );
 }
 
-fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+/// step or attack in the given direction, or wait a turn for (0, 0)
+fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, world: &mut World) {
+    let player = world.player;
+    if (dx, dy) == (0, 0) {
+        scheduler::spend(player, WAIT_COST, world);
+        return;
+    }
+
     // the coordinates the player is moving to/attacking
-    let x = objects[PLAYER].x + dx;
-    let y = objects[PLAYER].y + dy;
+    let (x, y) = match world.pos(player) {
+        Some((x, y)) => (x + dx, y + dy),
+        None => return,
+    };
 
-    // try to find an attackable object there
-    let target_id = objects
+    // try to find an attackable entity there
+    let target = world
+        .fighters
         .iter()
-        .position(|object| object.fighter.is_some() && object.pos() == (x, y));
+        .map(|(entity, _)| entity)
+        .find(|&entity| entity != player && world.pos(entity) == Some((x, y)));
 
     // attack if target found, move otherwise
-    match target_id {
-        Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
-            player.attack(target, game);
-        }
-        None => domain::move_by(PLAYER, dx, dy, &game.map, objects),
+    match target {
+        Some(target) => domain::attack(player, target, game, world),
+        None => domain::move_by(player, dx, dy, &game.map, world),
     }
 }
 
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
-    let (x, y) = (mouse.cx as i32, mouse.cy as i32);
-
-    // create a list with the names of all objects at the mouse's coordinates and in FOV
-    let names = objects
+fn get_names_at((x, y): (i32, i32), world: &World, fov_map: &FovMap) -> String {
+    // create a list with the names of all entities at the coordinates and in FOV
+    let names = world
+        .names
         .iter()
-        .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
-        .map(|obj| obj.name.clone())
+        .filter(|&(entity, _)| world.pos(entity) == Some((x, y)) && fov_map.is_in_fov(x, y))
+        .map(|(entity, _)| domain::item_label(entity, world))
         .collect::<Vec<_>>();
 
     names.join(", ")
 }
 
-/// add to the player's inventory and remove from the map
-fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
-    if game.inventory.len() >= MAX_INVENTORY {
-        game.messages.add(
-            format!(
-                "Your inventory is full, cannot pick up {}.",
-                objects[object_id].name
-            ),
-            RED,
-        );
-    } else {
-        let item = objects.swap_remove(object_id);
-        game.messages
-            .add(format!("You pick up a {}!", item.name), GREEN);
-        game.inventory.push(item);
+/// whether the player is still standing
+fn player_alive(world: &World) -> bool {
+    world.fighters.get(world.player).is_some_and(|f| f.hp > 0)
+}
+
+/// find closest enemy, up to a maximum range, and in the player's FOV
+fn closest_monster(game: &Game, world: &World, max_range: i32) -> Option<Entity> {
+    let player = world.positions.get(world.player)?;
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) maximum range
+
+    for (entity, _) in world.fighters.iter() {
+        let position = match world.positions.get(entity) {
+            Some(position) => position,
+            None => continue,
+        };
+        if entity != world.player
+            && world.ais.contains(entity)
+            && game.fov.is_in_fov(position.x, position.y)
+        {
+            // calculate distance between this entity and the player
+            let dist = player.distance_to(position);
+            if dist < closest_dist {
+                // it's closer, so remember it
+                closest_enemy = Some(entity);
+                closest_dist = dist;
+            }
+        }
     }
+
+    closest_enemy
+}
+
+/// add to the player's inventory, onto the stack of the same kind if they have one, and
+/// remove from the map
+fn pick_item_up(item: Entity, game: &mut Game, world: &mut World) {
+    world.positions.remove(item);
+    game.messages.add(
+        format!("You pick up {}!", domain::item_amount(item, world)),
+        GREEN,
+    );
+    domain::put_in_inventory(world.player, item, world);
 }
 
-fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
-    use tcod::input::KeyCode::*;
+fn handle_keys(tcod: &mut Tcod, game: &mut Game, world: &mut World) -> PlayerAction {
     use PlayerAction::*;
 
-    match (tcod.key, tcod.key.text(), objects[PLAYER].alive) {
-        (
-            Key {
-                code: Enter,
-                alt: true,
-                ..
-            },
-            _,
-            _,
-        ) => {
-            // Alt+Enter: toggle fullscreen
+    let player = world.player;
+    let action = match tcod.keymap.action(tcod.key) {
+        Some(action) => action,
+        None => return DidntTakeTurn,
+    };
+    match (action, player_alive(world)) {
+        (Action::Fullscreen, _) => {
             let fullscreen = tcod.root.is_fullscreen();
             tcod.root.set_fullscreen(!fullscreen);
             DidntTakeTurn
         }
-        (Key { code: Escape, .. }, _, _) => Exit, // exit game
+        (Action::Exit, _) => Exit, // save and exit game
+        (Action::Look, _) => {
+            let keymap = tcod.keymap.clone();
+            look::look_around(tcod, &keymap, game, world);
+            DidntTakeTurn
+        }
+        (Action::History, _) => {
+            history::show_history(&game.messages, tcod);
+            DidntTakeTurn
+        }
+        (Action::Help, _) => {
+            let help = format!(
+                "Keys\n\n{}\n\nPress any key to go back.",
+                tcod.keymap.help()
+            );
+            msgbox(&help, HELP_SCREEN_WIDTH, tcod);
+            DidntTakeTurn
+        }
 
-        // movement keys
-        (Key { code: Up, .. }, _, true) => {
-            player_move_or_attack(0, -1, game, objects);
+        // movement keys, waiting included
+        (action, true) if action.direction().is_some() => {
+            let (dx, dy) = action.direction().unwrap();
+            player_move_or_attack(dx, dy, game, world);
             TookTurn
         }
-        (Key { code: Down, .. }, _, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
+        (Action::PickUp, true) => {
+            // pick up an item, asking which one when there are several
+            let items: Vec<Entity> = world
+                .items
+                .iter()
+                .map(|(item, _)| item)
+                .filter(|&item| world.pos(item) == world.pos(player))
+                .collect();
+            if items.len() > 1 {
+                let mut options: Vec<String> = items
+                    .iter()
+                    .map(|&item| domain::item_label(item, world))
+                    .collect();
+                options.push("Everything".into());
+                let choice = menu(
+                    "Press the key next to an item to pick it up, or any other key to cancel.\n",
+                    &options,
+                    game.config.inventory_width,
+                    tcod,
+                );
+                match choice {
+                    Some(index) if index == items.len() => {
+                        for &item in &items {
+                            pick_item_up(item, game, world);
+                        }
+                    }
+                    Some(index) => pick_item_up(items[index], game, world),
+                    None => {}
+                }
+            } else if let Some(&item) = items.first() {
+                pick_item_up(item, game, world);
+            }
+            DidntTakeTurn
         }
-        (Key { code: Left, .. }, _, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
+        (Action::Drop, true) => {
+            let item = inventory_menu(
+                player,
+                world,
+                "Press the key next to an item to drop it, or any other key to cancel.\n",
+                game.config.inventory_width,
+                tcod,
+            );
+            if let Some(item) = item {
+                drop_item(item, game, world);
+            }
+            DidntTakeTurn
         }
-        (Key { code: Right, .. }, _, true) => {
-            player_move_or_attack(1, 0, game, objects);
-            TookTurn
+        (Action::Throw, true) => {
+            let item = inventory_menu(
+                player,
+                world,
+                "Press the key next to an item to throw it, or any other key to cancel.\n",
+                game.config.inventory_width,
+                tcod,
+            );
+            match item {
+                Some(item) => throw_item(item, tcod, game, world),
+                None => DidntTakeTurn,
+            }
         }
-        (Key { code: Text, .. }, "g", true) => {
-            // pick up an item
-            let item_id = objects
+        (Action::Descend, true) => {
+            // go down stairs, if the player is on them
+            let player_on_stairs = world
+                .names
                 .iter()
-                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, game, objects);
+                .any(|(entity, name)| name == "stairs" && world.pos(entity) == world.pos(player));
+            if player_on_stairs {
+                next_level(game, world);
             }
             DidntTakeTurn
         }
-        (Key { code: Text, .. }, "i", true) => {
+        (Action::Inventory, true) => {
             // show the inventory
-            let inventory_index = inventory_menu(
-                &game.inventory,
+            let item = inventory_menu(
+                player,
+                world,
                 "Press the key next to an item to use it, or any other key to cancel.\n",
-                &mut tcod.root,
+                game.config.inventory_width,
+                tcod,
             );
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, tcod, game, objects);
+            match item {
+                Some(item) => use_item(item, tcod, game, world),
+                None => DidntTakeTurn,
             }
-            DidntTakeTurn
         }
         _ => DidntTakeTurn,
     }
 }
 
-fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+/// experience needed to advance past the given character level
+fn level_up_xp(level: i32) -> i32 {
+    LEVEL_UP_BASE + level * LEVEL_UP_FACTOR
+}
+
+/// see if the player's experience is enough to level-up, and let them pick a stat to raise
+fn level_up(renderer: &mut impl Renderer, game: &mut Game, world: &mut World) {
+    let fighter = match world.fighters.get_mut(world.player) {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    let level_up_xp = level_up_xp(fighter.level);
+
+    // see if the player's experience is enough to level-up
+    if fighter.xp >= level_up_xp {
+        let mut choice = None;
+        while choice.is_none() {
+            // keep asking until a choice is made, unless the game is being closed; the
+            // level-up then waits for the game to be continued
+            if renderer.window_closed() {
+                return;
+            }
+            choice = menu(
+                "Level up! Choose a stat to raise:\n",
+                &[
+                    format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+                    format!("Strength (+1 attack, from {})", fighter.base_power),
+                    format!("Agility (+1 defense, from {})", fighter.base_defense),
+                ],
+                LEVEL_SCREEN_WIDTH,
+                renderer,
+            );
+        }
+
+        // it is! level up
+        fighter.level += 1;
+        game.messages.add(
+            format!(
+                "Your battle skills grow stronger! You reached level {}!",
+                fighter.level
+            ),
+            YELLOW,
+        );
+        fighter.xp -= level_up_xp;
+        match choice.unwrap() {
+            0 => {
+                fighter.base_max_hp += 20;
+                fighter.hp += 20;
+            }
+            1 => fighter.base_power += 1,
+            2 => fighter.base_defense += 1,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Advance to the next level
+fn next_level(game: &mut Game, world: &mut World) {
+    let player = world.player;
+    game.messages.add(
+        "You take a moment to rest, and recover your strength.",
+        VIOLET,
+    );
+    let heal_hp = domain::max_hp(player, world) / 2;
+    domain::heal(player, heal_hp, world);
+
+    game.messages.add(
+        "After a rare moment of peace, you descend deeper into the heart of the dungeon...",
+        RED,
+    );
+    game.dungeon_level += 1;
+
+    // everything on the map but the player stays behind on the old level, along with what
+    // the monsters carry; what the player carries has no position and comes along
+    for entity in world.positions.entities() {
+        if entity != player {
+            world.despawn(entity);
+        }
+    }
+    game.map = make_map(
+        world,
+        game.dungeon_level,
+        &game.config,
+        &game.templates,
+        &mut game.rng,
+    );
+    initialise_fov(game);
+}
+
+/// use the item from the player's inventory, which takes time unless it was cancelled
+fn use_item(item: Entity, tcod: &mut Tcod, game: &mut Game, world: &mut World) -> PlayerAction {
     use Item::*;
     // just call the use_function if it is defined
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
+    if let Some(&effect) = world.items.get(item) {
+        let on_use = match effect {
             Heal => cast_heal,
+            Equip => toggle_equipment,
+            Lightning => cast_lightning,
+            Fireball => cast_fireball,
+            Confuse => cast_confuse,
+            Haste => drink_haste,
+            Slow => drink_slowness,
         };
 
-        match on_use(inventory_id, tcod, game, objects) {
+        let result = on_use(item, tcod, game, world);
+        if let UseResult::UsedUp | UseResult::UsedAndKept = result {
+            scheduler::spend(world.player, USE_ITEM_COST, world);
+        }
+        match result {
             UseResult::UsedUp => {
-                // destroy after use, unless it was cancelled for some reason
-                game.inventory.remove(inventory_id);
+                // destroy after use, unless it was cancelled for some reason; of a stack,
+                // only the one that was used
+                domain::use_up(world.player, item, world);
             }
+            UseResult::UsedAndKept => {} // the item stays in the inventory
             UseResult::Cancelled => {
                 game.messages.add("Cancelled", WHITE);
+                return PlayerAction::DidntTakeTurn;
             }
         }
+        PlayerAction::TookTurn
+    } else {
+        game.messages
+            .add(format!("The {} cannot be used.", world.name(item)), WHITE);
+        PlayerAction::DidntTakeTurn
+    }
+}
+
+/// put the item from the player's inventory down where they stand
+fn drop_item(item: Entity, game: &mut Game, world: &mut World) {
+    let player = world.player;
+    let (x, y) = match world.pos(player) {
+        Some(pos) => pos,
+        None => return,
+    };
+    take_off(item, game, world);
+    world.take_from_inventory(player, item);
+    world.set_pos(item, x, y);
+    game.messages.add(
+        format!("You dropped {}.", domain::item_amount(item, world)),
+        YELLOW,
+    );
+}
+
+/// throw the item from the player's inventory at a tile; it lands where it stops, unless it's
+/// a potion, which shatters and splashes whoever it hit
+fn throw_item(item: Entity, tcod: &mut Tcod, game: &mut Game, world: &mut World) -> PlayerAction {
+    let player = world.player;
+    let start = match world.pos(player) {
+        Some(pos) => pos,
+        None => return PlayerAction::DidntTakeTurn,
+    };
+    game.messages.add(
+        format!(
+            "Choose where to throw the {} and press Enter, or Escape to cancel.",
+            world.name(item)
+        ),
+        LIGHT_CYAN,
+    );
+    let keymap = tcod.keymap.clone();
+    let target = choose_target(
+        tcod,
+        &keymap,
+        game,
+        world,
+        TargetKind::Throw,
+        Some(THROW_RANGE as f32),
+    );
+    let (x, y) = match target {
+        Some(Target::Tile(x, y)) => (x, y),
+        _ => {
+            game.messages.add("Cancelled", WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+    };
+
+    let path = targeting::flight_path(start, (x, y), &game.map, world);
+    let (land_x, land_y) = path.last().copied().unwrap_or(start);
+    let hit = world
+        .fighters
+        .entities()
+        .into_iter()
+        .find(|&entity| entity != player && world.pos(entity) == Some((land_x, land_y)));
+
+    // only one of a stack flies
+    let item = domain::take_one(item, world);
+    take_off(item, game, world);
+    world.take_from_inventory(player, item);
+    scheduler::spend(player, ATTACK_COST, world);
+
+    let name = world.name(item).to_string();
+    match (world.items.get(item).copied(), hit) {
+        (Some(effect), Some(monster)) if effect.shatters() => {
+            game.messages.add(
+                format!("The {} shatters over the {}!", name, world.name(monster)),
+                LIGHT_CYAN,
+            );
+            world.despawn(item);
+            splash(effect, monster, game, world);
+        }
+        (Some(effect), None) if effect.shatters() => {
+            game.messages
+                .add(format!("The {} shatters on the floor.", name), LIGHT_GREY);
+            world.despawn(item);
+        }
+        (_, Some(monster)) => {
+            game.messages.add(
+                format!("The {} bounces off the {}.", name, world.name(monster)),
+                LIGHT_GREY,
+            );
+            world.set_pos(item, land_x, land_y);
+        }
+        (_, None) => {
+            game.messages
+                .add(format!("The {} lands on the floor.", name), LIGHT_GREY);
+            world.set_pos(item, land_x, land_y);
+        }
+    }
+    PlayerAction::TookTurn
+}
+
+/// what a potion does to whoever drinks it or has it shattered over them
+fn splash(effect: Item, target: Entity, game: &mut Game, world: &mut World) {
+    let (you, it, color) = match effect {
+        Item::Heal => {
+            domain::heal(target, game.config.heal_amount, world);
+            (
+                "Your wounds start to feel better!",
+                "looks healthier.",
+                LIGHT_VIOLET,
+            )
+        }
+        Item::Haste => {
+            boost_speed(target, Boost::Haste, world);
+            ("You feel yourself speed up!", "speeds up!", LIGHT_BLUE)
+        }
+        Item::Slow => {
+            boost_speed(target, Boost::Slow, world);
+            (
+                "You feel yourself slow down...",
+                "slows down.",
+                LIGHT_VIOLET,
+            )
+        }
+        _ => return,
+    };
+
+    if target == world.player {
+        game.messages.add(you, color);
+    } else if world
+        .pos(target)
+        .is_some_and(|(x, y)| game.fov.is_in_fov(x, y))
+    {
+        game.messages
+            .add(format!("The {} {}", world.name(target), it), color);
+    }
+}
+
+fn cast_heal(_item: Entity, _tcod: &mut Tcod, game: &mut Game, world: &mut World) -> UseResult {
+    let player = world.player;
+    if let Some(fighter) = world.fighters.get(player) {
+        if fighter.hp == domain::max_hp(player, world) {
+            game.messages.add("You are already at full health.", RED);
+            return UseResult::Cancelled;
+        }
+        splash(Item::Heal, player, game, world);
+        return UseResult::UsedUp;
+    }
+
+    UseResult::Cancelled
+}
+
+fn cast_lightning(
+    _item: Entity,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    // find closest enemy (inside a maximum range) and damage it
+    let monster = closest_monster(game, world, LIGHTNING_RANGE);
+    if let Some(monster) = monster {
+        // zap it!
+        game.messages.add(
+            format!(
+                "A lightning bolt strikes the {} with a loud thunder! \
+                 The damage is {} hit points.",
+                world.name(monster),
+                LIGHTNING_DAMAGE
+            ),
+            LIGHT_BLUE,
+        );
+        if let Some(xp) = domain::take_damage(monster, LIGHTNING_DAMAGE, game, world) {
+            domain::gain_xp(world.player, xp, game, world);
+        }
+        UseResult::UsedUp
     } else {
+        // no enemy found within maximum range
+        game.messages
+            .add("No enemy is close enough to strike.", RED);
+        UseResult::Cancelled
+    }
+}
+
+fn cast_fireball(_item: Entity, tcod: &mut Tcod, game: &mut Game, world: &mut World) -> UseResult {
+    // ask the player for a target tile to throw a fireball at
+    game.messages.add(
+        "Choose a target tile for the fireball and press Enter, or Escape to cancel.",
+        LIGHT_CYAN,
+    );
+    let keymap = tcod.keymap.clone();
+    let (x, y) = match choose_target(tcod, &keymap, game, world, TargetKind::Tile, None) {
+        Some(Target::Tile(x, y)) => (x, y),
+        _ => return UseResult::Cancelled,
+    };
+    game.messages.add(
+        format!(
+            "The fireball explodes, burning everything within {} tiles!",
+            FIREBALL_RADIUS
+        ),
+        ORANGE,
+    );
+
+    let burned: Vec<Entity> = world
+        .fighters
+        .entities()
+        .into_iter()
+        .filter(|&entity| {
+            world
+                .positions
+                .get(entity)
+                .is_some_and(|position| position.distance(x, y) <= FIREBALL_RADIUS as f32)
+        })
+        .collect();
+
+    for entity in burned {
+        game.messages.add(
+            format!(
+                "The {} gets burned for {} hit points.",
+                world.name(entity),
+                FIREBALL_DAMAGE
+            ),
+            ORANGE,
+        );
+        if let Some(xp) = domain::take_damage(entity, FIREBALL_DAMAGE, game, world) {
+            // the player can burn themselves, but gets no experience for it
+            if entity != world.player {
+                domain::gain_xp(world.player, xp, game, world);
+            }
+        }
+    }
+
+    UseResult::UsedUp
+}
+
+fn cast_confuse(_item: Entity, tcod: &mut Tcod, game: &mut Game, world: &mut World) -> UseResult {
+    // ask the player for a target to confuse
+    game.messages.add(
+        "Choose an enemy to confuse and press Enter, or Escape to cancel.",
+        LIGHT_CYAN,
+    );
+    let keymap = tcod.keymap.clone();
+    let target = choose_target(
+        tcod,
+        &keymap,
+        game,
+        world,
+        TargetKind::Monster,
+        Some(CONFUSE_RANGE as f32),
+    );
+    if let Some(Target::Monster(monster)) = target {
+        let old_ai = world.ais.remove(monster).unwrap_or(Ai::Basic);
+        // replace the monster's AI with a "confused" one; after
+        // some turns it will restore the old AI
+        world.ais.insert(
+            monster,
+            Ai::Confused {
+                previous_ai: Box::new(old_ai),
+                num_turns: CONFUSE_NUM_TURNS,
+            },
+        );
         game.messages.add(
-            format!("The {} cannot be used.", game.inventory[inventory_id].name),
-            WHITE,
+            format!(
+                "The eyes of {} look vacant, as it starts to stumble around!",
+                world.name(monster)
+            ),
+            LIGHT_GREEN,
         );
+        UseResult::UsedUp
+    } else {
+        // no enemy found within maximum range
+        game.messages
+            .add("No enemy is close enough to strike.", RED);
+        UseResult::Cancelled
     }
 }
 
-fn cast_heal(
-    _inventory_id: usize,
+fn drink_haste(_item: Entity, _tcod: &mut Tcod, game: &mut Game, world: &mut World) -> UseResult {
+    splash(Item::Haste, world.player, game, world);
+    UseResult::UsedUp
+}
+
+fn drink_slowness(
+    _item: Entity,
     _tcod: &mut Tcod,
     game: &mut Game,
-    objects: &mut [Object],
+    world: &mut World,
 ) -> UseResult {
-    if let Some(fighter) = objects[PLAYER].fighter {
-        if fighter.hp == fighter.max_hp {
-            game.messages.add("You are already at full health.", RED);
-            return UseResult::Cancelled;
+    splash(Item::Slow, world.player, game, world);
+    UseResult::UsedUp
+}
+
+/// haste or slow the entity for a while, replacing whatever boost it had
+fn boost_speed(entity: Entity, boost: Boost, world: &mut World) {
+    if let Some(actor) = world.actors.get_mut(entity) {
+        actor.boost = Some((boost, SPEED_POTION_TURNS));
+    }
+}
+
+fn toggle_equipment(
+    item: Entity,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    let equipment = match world.equipment.get(item) {
+        Some(&equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    let player = world.player;
+
+    if equipment.equipped {
+        take_off(item, game, world);
+    } else {
+        // if the slot is already being used, dequip whatever is there first
+        if let Some(current) = get_equipped_in_slot(equipment.slot, player, world) {
+            domain::dequip(current, world, &mut game.messages);
         }
-        game.messages
-            .add("Your wounds start to feel better!", LIGHT_VIOLET);
-        objects[PLAYER].heal(HEAL_AMOUNT);
-        return UseResult::UsedUp;
+        domain::equip(item, world, &mut game.messages);
     }
 
-    UseResult::Cancelled
+    UseResult::UsedAndKept
 }
 
+/// dequip the item if the player has it on
+fn take_off(item: Entity, game: &mut Game, world: &mut World) {
+    if !world.equipment.get(item).is_some_and(|e| e.equipped) {
+        return;
+    }
+    domain::dequip(item, world, &mut game.messages);
+
+    // losing a max HP bonus can leave the player above their new maximum
+    let player = world.player;
+    let max_hp = domain::max_hp(player, world);
+    if let Some(fighter) = world.fighters.get_mut(player) {
+        fighter.hp = cmp::min(fighter.hp, max_hp);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn render_bar(
-    panel: &mut Offscreen,
+    renderer: &mut impl Renderer,
     x: i32,
     y: i32,
     total_width: i32,
@@ -542,68 +1413,131 @@ fn render_bar(
     let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
 
     // render the background first
-    panel.set_default_background(back_color);
-    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+    renderer.fill_background(x, y, total_width, 1, back_color);
 
     // render the bar on top
     if bar_width > 0 {
-        panel.set_default_background(bar_color);
-        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+        renderer.fill_background(x, y, bar_width, 1, bar_color);
     }
 
     // text with values
-    panel.set_default_foreground(WHITE);
-    panel.print_ex(
+    renderer.print(
         x + total_width / 2,
         y,
-        BackgroundFlag::None,
-        TextAlignment::Center,
         &format!("{}: {}/{}", name, value, maximum),
+        WHITE,
+        TextAlignment::Center,
     );
 }
 
-fn main() {
-    tcod::system::set_fps(LIMIT_FPS);
+/// read the dungeon seed from `--seed <n>` on the command line, if one was given
+fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
 
-    let root = Root::initializer()
-        .font(FONT_PATH, FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
-        .title("Rust/libtcod tutorial")
-        .init();
+    match args.iter().position(|arg| arg == "--seed") {
+        Some(index) => match args.get(index + 1).map(|seed| seed.parse()) {
+            Some(Ok(seed)) => Some(seed),
+            _ => {
+                eprintln!("--seed expects a non-negative integer");
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    }
+}
 
-    let mut tcod = Tcod {
-        root,
-        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
-        key: Default::default(),
-        mouse: Default::default(),
-    };
+/// read the config file given by `--config <path>`, or the default one if it exists, and
+/// apply every `--set section.key=value` on top of it
+fn config_from_args() -> Result<Config, toml::DataError> {
+    let args: Vec<String> = std::env::args().collect();
+    let mut path = None;
+    let mut overrides = vec![];
+
+    for (index, arg) in args.iter().enumerate() {
+        if arg == "--config" || arg == "--set" {
+            let value = match args.get(index + 1) {
+                Some(value) => value.clone(),
+                None => {
+                    eprintln!("{} expects a value", arg);
+                    std::process::exit(2);
+                }
+            };
+            if arg == "--config" {
+                path = Some(value);
+            } else {
+                overrides.push(value);
+            }
+        }
+    }
+
+    match path {
+        Some(path) => Config::load(&path, true, &overrides),
+        None => Config::load(CONFIG_PATH, false, &overrides),
+    }
+}
+
+/// create the player and a freshly generated dungeon for the given seed
+fn new_game(seed: u64, config: &Config, templates: &Templates) -> (Game, World) {
+    // create the entity representing the player, the map generator puts it at the start
+    let mut world = World::new();
+    let player = spawn_at(&mut world, 0, 0, '@', "player", WHITE, true);
+    world
+        .fighters
+        .insert(player, Fighter::new(2, 30, 30, 5, 0, DeathCallback::Player));
+    world.inventories.insert(player, Inventory::default());
+    world.actors.insert(player, Actor::new(NORMAL_SPEED));
+    world.player = player;
+
+    let mut game = empty_game(seed, config, templates);
+
+    // generate map (at this point it's not drawn to the screen)
+    game.map = make_map(
+        &mut world,
+        game.dungeon_level,
+        &game.config,
+        &game.templates,
+        &mut game.rng,
+    );
+    initialise_fov(&mut game);
 
-    // create object representing the player
-    let mut player = Object::new(0, 0, '@', "player", WHITE, true);
-    player.alive = true;
-    player.fighter = Some(Fighter::new(2, 30, 30, 5, DeathCallback::Player));
+    // a warm welcoming message!
+    game.messages.add(
+        "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
+        RED,
+    );
 
-    // the list of objects
-    // ID for player will always be fixed at 0, but everything else can change
-    let mut objects = vec![player];
+    (game, world)
+}
 
-    let mut game = Game {
-        // generate map (at this point it's not drawn to the screen)
-        map: make_map(&mut objects),
-        messages: Messages::new(),
-        inventory: vec![],
-    };
+/// a game with nothing in it yet, to be filled by generating or loading a dungeon
+fn empty_game(seed: u64, config: &Config, templates: &Templates) -> Game {
+    Game {
+        map: vec![],
+        fov: FovMap::new(config.map_width, config.map_height),
+        messages: Messages::new(config.message_limit as usize),
+        dungeon_level: 1,
+        seed,
+        // every random roll in the game comes from this generator, so a seed
+        // always reproduces the same dungeon
+        rng: StdRng::seed_from_u64(seed),
+        config: config.clone(),
+        templates: templates.clone(),
+    }
+}
 
-    // force FOV recompute first time through the game loop
-    let mut previous_player_position = (-1, -1);
+/// restore the game from the save file
+fn load_game(config: &Config, templates: &Templates) -> Result<(Game, World), save::LoadError> {
+    let mut game = empty_game(0, config, templates);
+    let world = save::load_game(&mut game)?;
+    initialise_fov(&mut game);
+    Ok((game, world))
+}
 
-    // populate the FOV map according to the generated map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(
+/// populate the FOV map according to the generated map
+fn initialise_fov(game: &mut Game) {
+    for y in 0..game.config.map_height {
+        for x in 0..game.config.map_width {
+            game.fov.set(
                 x,
                 y,
                 // invert our domain's `block_sight` to match tcod's `transparent`
@@ -613,18 +1547,15 @@ fn main() {
             )
         }
     }
+}
 
-    // a warm welcoming message!
-    game.messages.add(
-        "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
-        RED,
-    );
+fn play_game(tcod: &mut Tcod, game: &mut Game, world: &mut World) {
+    // force FOV recompute first time through the game loop
+    let mut previous_player_position = None;
+    let mut previous_dungeon_level = 0;
 
     // game loop
     while !tcod.root.window_closed() {
-        // clear the screen of the previous frame
-        tcod.con.clear();
-
         match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
             Some((_, Event::Mouse(m))) => tcod.mouse = m,
             Some((_, Event::Key(k))) => tcod.key = k,
@@ -632,25 +1563,329 @@ fn main() {
         }
 
         // render the screen
-        let should_compute_fov = previous_player_position != objects[PLAYER].pos();
-        render_all(&mut tcod, &mut game, &objects, should_compute_fov);
+        let should_compute_fov = previous_player_position != world.pos(world.player)
+            || previous_dungeon_level != game.dungeon_level;
+        let mouse_position = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+        render_all(tcod, mouse_position, game, world, should_compute_fov);
 
-        tcod.root.flush();
+        tcod.flush();
 
         // handle keys and exit game if needed
-        previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(&mut tcod, &mut game, &mut objects);
+        previous_player_position = world.pos(world.player);
+        previous_dungeon_level = game.dungeon_level;
+        let player_action = handle_keys(tcod, game, world);
         if player_action == PlayerAction::Exit {
+            if let Err(error) = save::save_game(game, world) {
+                msgbox(&format!("\nCould not save the game: {}\n", error), 24, tcod);
+            }
             break;
         }
 
-        // let monsters take their turn
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, &tcod, &mut game, &mut objects);
-                }
+        level_up(tcod, game, world);
+
+        // let the monsters act until it's the player's turn again
+        if player_action != PlayerAction::DidntTakeTurn {
+            take_monster_turns(game, world);
+        }
+    }
+}
+
+/// run the turns of everyone who gets to act before the player, fast monsters getting more
+/// of them than slow ones
+fn take_monster_turns(game: &mut Game, world: &mut World) {
+    let player = world.player;
+    while player_alive(world) {
+        let actor = match scheduler::next_actor(world) {
+            Some(actor) => actor,
+            None => return,
+        };
+        if let Some(boost) = scheduler::start_turn(actor, world) {
+            if actor == player {
+                let message = match boost {
+                    Boost::Haste => "You feel yourself slow down.",
+                    Boost::Slow => "You feel yourself speed up.",
+                };
+                game.messages.add(message, LIGHT_GREY);
+            }
+        }
+        if actor == player {
+            return;
+        }
+
+        if world.ais.contains(actor) {
+            ai_take_turn(actor, game, world);
+        } else {
+            // something that takes turns but can't decide what to do with them
+            scheduler::spend(actor, WAIT_COST, world);
+        }
+    }
+}
+
+fn main_menu(tcod: &mut Tcod, seed: Option<u64>, config: &Config, templates: &Templates) {
+    while !tcod.root.window_closed() {
+        // show the game's title
+        let (screen_width, screen_height) = tcod.size();
+        tcod.clear();
+        tcod.print(
+            screen_width / 2,
+            screen_height / 2 - 4,
+            "TOMBS OF THE ANCIENT KINGS",
+            LIGHT_YELLOW,
+            TextAlignment::Center,
+        );
+
+        // show options and wait for the player's choice
+        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choice = menu("", choices, 24, tcod);
+
+        match choice {
+            Some(0) => {
+                // new game, from the seed on the command line if there was one
+                let (mut game, mut world) =
+                    new_game(seed.unwrap_or_else(rand::random), config, templates);
+                play_game(tcod, &mut game, &mut world);
+            }
+            Some(1) => match load_game(config, templates) {
+                Ok((mut game, mut world)) => play_game(tcod, &mut game, &mut world),
+                Err(error) => msgbox(&format!("\nNo saved game to load: {}\n", error), 24, tcod),
+            },
+            Some(2) => break,
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    let seed = seed_from_args();
+
+    // the window, the generator and the balance come from the config file, `--set` tweaks it
+    let config = match config_from_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Could not load the configuration: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // monsters and items come from the templates file, refuse to start if it's broken
+    let templates = match Templates::load(&config.templates_path) {
+        Ok(templates) => templates,
+        Err(error) => {
+            eprintln!("Could not load the monster and item templates: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // a keymap file is only needed when the config points somewhere else than the default
+    let keymap = match Keymap::load(&config.keymap_path, config.keymap_path != KEYMAP_PATH) {
+        Ok(keymap) => keymap,
+        Err(error) => {
+            eprintln!("Could not load the keymap: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    if std::env::args().any(|arg| arg == "--snapshot") {
+        // draw the first frame without opening a window and print it, handy next to a seed
+        let (mut game, world) = new_game(seed.unwrap_or_else(rand::random), &config, &templates);
+        let mut grid = GridRenderer::new(config.screen_width, config.screen_height);
+        render_all(&mut grid, (-1, -1), &mut game, &world, true);
+        println!("{}", grid.snapshot());
+        return;
+    }
+
+    tcod::system::set_fps(config.limit_fps);
+
+    let root = Root::initializer()
+        .font(&config.font_path, FontLayout::Tcod)
+        .font_type(FontType::Greyscale)
+        .size(config.screen_width, config.screen_height)
+        .title("Rust/libtcod tutorial")
+        .init();
+
+    let mut tcod = Tcod {
+        root,
+        key: Default::default(),
+        mouse: Default::default(),
+        keymap,
+    };
+
+    main_menu(&mut tcod, seed, &config, &templates);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+
+    fn setup() -> (Config, Templates) {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        let templates = Templates::load(&config.templates_path).unwrap();
+        (config, templates)
+    }
+
+    /// the first frame of a new game, drawn without a window
+    fn first_frame(
+        seed: u64,
+        config: &Config,
+        templates: &Templates,
+    ) -> (GridRenderer, Game, World) {
+        let (mut game, world) = new_game(seed, config, templates);
+        let mut grid = GridRenderer::new(config.screen_width, config.screen_height);
+        render_all(&mut grid, (-1, -1), &mut game, &world, true);
+        (grid, game, world)
+    }
+
+    fn row(grid: &GridRenderer, y: i32) -> String {
+        grid.snapshot().lines().nth(y as usize).unwrap().to_string()
+    }
+
+    #[test]
+    fn a_new_game_draws_the_map_and_the_panel() {
+        let (config, templates) = setup();
+        let (grid, game, world) = first_frame(SEED, &config, &templates);
+
+        let (x, y) = world.pos(world.player).unwrap();
+        assert_eq!(grid.cell(x, y).glyph, '@');
+        // monsters in sight, drawn over anything lying under them
+        for (entity, position) in world.positions.iter() {
+            if game.fov.is_in_fov(position.x, position.y) && world.blocks.contains(entity) {
+                let glyph = world.renderables.get(entity).unwrap().glyph;
+                assert_eq!(grid.cell(position.x, position.y).glyph, glyph);
+            }
+        }
+
+        // lit tiles around the player, nothing drawn where they haven't been
+        for x in 0..config.map_width {
+            for y in 0..config.map_height {
+                let wall = game.map[x as usize][y as usize].block_sight;
+                let expected = match (game.fov.is_in_fov(x, y), wall) {
+                    (true, true) => config.color_light_wall,
+                    (true, false) => config.color_light_ground,
+                    (false, _) => BLACK,
+                };
+                assert_eq!(grid.cell(x, y).background, expected, "tile {}, {}", x, y);
+            }
+        }
+
+        let panel_y = config.panel_y();
+        assert!(row(&grid, panel_y + 1).contains("HP: 30/30"));
+        assert!(row(&grid, panel_y + 2).contains("XP: 0/350"));
+        assert!(row(&grid, panel_y + 3).starts_with(" Dungeon level: 1"));
+        assert!(row(&grid, panel_y + 4).starts_with(" Character level: 1"));
+        assert!(row(&grid, panel_y + 6).starts_with(&format!(" Seed: {}", SEED)));
+        let message = &row(&grid, panel_y + 4)[config.msg_x() as usize..];
+        assert!(message.starts_with("Welcome stranger!"));
+    }
+
+    #[test]
+    fn leveling_up_waits_for_a_choice_unless_the_window_closes() {
+        let (config, templates) = setup();
+        let (mut game, mut world) = new_game(SEED, &config, &templates);
+        let player = world.player;
+        world.fighters.get_mut(player).unwrap().xp = level_up_xp(1);
+
+        // nothing to read, as with a closed window
+        let mut grid = GridRenderer::new(config.screen_width, config.screen_height);
+        level_up(&mut grid, &mut game, &mut world);
+        assert_eq!(world.fighters.get(player).unwrap().level, 1);
+
+        grid.events.extend(render::scripted::typed("b"));
+        level_up(&mut grid, &mut game, &mut world);
+        let fighter = world.fighters.get(player).unwrap();
+        assert_eq!((fighter.level, fighter.xp, fighter.base_power), (2, 0, 6));
+    }
+
+    #[test]
+    fn potions_say_the_same_however_they_get_to_you() {
+        let (config, templates) = setup();
+        let (mut game, mut world) = new_game(SEED, &config, &templates);
+        let player = world.player;
+
+        // drunk, then shattered over the player by a monster
+        splash(Item::Slow, player, &mut game, &mut world);
+        splash(Item::Slow, player, &mut game, &mut world);
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.display(), "You feel yourself slow down... (x2)");
+        assert_eq!(
+            world.actors.get(player).unwrap().boost,
+            Some((Boost::Slow, SPEED_POTION_TURNS))
+        );
+    }
+
+    #[test]
+    fn only_the_player_hears_about_experience() {
+        let (config, templates) = setup();
+        let (mut game, mut world) = new_game(SEED, &config, &templates);
+        let player = world.player;
+        let monsters: Vec<_> = world
+            .ais
+            .entities()
+            .into_iter()
+            .filter(|&monster| monster != player)
+            .collect();
+        let (killer, victim, last) = (monsters[0], monsters[1], monsters[2]);
+        for &entity in &[killer, player] {
+            world.fighters.get_mut(entity).unwrap().base_power = 100;
+        }
+        let experience = |game: &Game| {
+            game.messages
+                .iter()
+                .filter(|message| message.display().contains("experience"))
+                .count()
+        };
+
+        domain::attack(killer, victim, &mut game, &mut world);
+        assert!(!world.fighters.contains(victim));
+        assert_eq!(experience(&game), 0);
+
+        let xp = world.fighters.get(last).unwrap().xp;
+        domain::attack(player, last, &mut game, &mut world);
+        let message = game.messages.iter().last().unwrap().display();
+        assert_eq!(message, format!("You gain {} experience points.", xp));
+        assert_eq!(world.fighters.get(player).unwrap().xp, xp);
+    }
+
+    #[test]
+    fn going_down_leaves_what_monsters_carry_behind() {
+        let (config, templates) = setup();
+        let (mut game, mut world) = new_game(SEED, &config, &templates);
+        let carried: Vec<_> = world
+            .inventories
+            .iter()
+            .filter(|&(owner, _)| owner != world.player)
+            .flat_map(|(_, inventory)| inventory.items.clone())
+            .collect();
+        assert!(!carried.is_empty());
+
+        next_level(&mut game, &mut world);
+        assert!(carried.iter().all(|&item| !world.contains(item)));
+        // nothing is left without a place or an owner
+        for entity in world.entities() {
+            let carried = world
+                .inventories
+                .iter()
+                .any(|(_, inventory)| inventory.items.contains(&entity));
+            assert!(world.positions.contains(entity) || carried);
+        }
+    }
+
+    #[test]
+    fn the_same_seed_draws_the_same_screen() {
+        let (config, templates) = setup();
+        let (first, first_game, _) = first_frame(SEED, &config, &templates);
+        let (second, second_game, _) = first_frame(SEED, &config, &templates);
+
+        assert_eq!(first.snapshot(), second.snapshot());
+        for x in 0..config.screen_width {
+            for y in 0..config.screen_height {
+                assert_eq!(first.cell(x, y), second.cell(x, y));
             }
         }
+        assert_eq!(
+            format!("{:?}", first_game.map),
+            format!("{:?}", second_game.map)
+        );
     }
 }
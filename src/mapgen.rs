@@ -0,0 +1,624 @@
+//! Dungeon layouts. A generator only decides where the walls and floors go, where the player
+//! starts and the stairs are, and which areas the monsters and items are spread over;
+//! `make_map` then puts everything in place.
+
+use crate::config::Config;
+use crate::dijkstra::DijkstraMap;
+use crate::domain::{can_step, Map, Rect, Tile};
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::cmp;
+use std::collections::VecDeque;
+
+/// Which generator lays out the levels, picked in the config
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    Rooms,
+    Bsp,
+    Caves,
+    Drunkard,
+    Mixed,
+}
+
+/// Open tiles that get as many monsters and items as a room would
+pub type Region = Vec<(i32, i32)>;
+
+/// The walls and floors of a new level and where things go on it
+pub struct Layout {
+    pub map: Map,
+    /// where the player arrives
+    pub start: (i32, i32),
+    /// where the stairs down are
+    pub stairs: (i32, i32),
+    pub regions: Vec<Region>,
+}
+
+impl Layout {
+    /// the player starts in the first room, the stairs are in the last one and every room is
+    /// a region
+    fn from_rooms(map: Map, rooms: &[Rect]) -> Self {
+        let regions = rooms
+            .iter()
+            .map(|room| {
+                ((room.x1 + 1)..room.x2)
+                    .flat_map(|x| ((room.y1 + 1)..room.y2).map(move |y| (x, y)))
+                    .collect()
+            })
+            .collect();
+        Layout {
+            map,
+            start: rooms[0].center(),
+            stairs: rooms[rooms.len() - 1].center(),
+            regions,
+        }
+    }
+}
+
+/// A way of laying out a level
+pub trait MapGenerator {
+    fn generate(&self, config: &Config, rng: &mut StdRng) -> Layout;
+}
+
+/// Up to `max_rooms` rooms thrown on the map at random, dropping the ones that overlap, each
+/// joined to the one before by a corridor
+pub struct RandomRooms;
+
+impl MapGenerator for RandomRooms {
+    fn generate(&self, config: &Config, rng: &mut StdRng) -> Layout {
+        let mut map = walls(config);
+        let mut rooms: Vec<Rect> = vec![];
+
+        for _ in 0..config.max_rooms {
+            // random width and height
+            let w = rng.gen_range(config.room_min_size, config.room_max_size + 1);
+            let h = rng.gen_range(config.room_min_size, config.room_max_size + 1);
+
+            // random position without going out of the boundaries of the map
+            let x = rng.gen_range(0, config.map_width - w);
+            let y = rng.gen_range(0, config.map_height - h);
+
+            let new_room = Rect::new(x, y, w, h);
+
+            // run through the other rooms and see if they intersect with this one
+            let has_intersections = rooms
+                .iter()
+                .any(|other_room| new_room.intersects_with(other_room));
+
+            if !has_intersections {
+                // paint it to the map's tiles
+                create_room(new_room, &mut map);
+
+                // all rooms after the first: connect to previous with a tunnel
+                if let Some(previous) = rooms.last() {
+                    connect(previous.center(), new_room.center(), &mut map, rng);
+                }
+
+                rooms.push(new_room);
+            }
+        }
+
+        Layout::from_rooms(map, &rooms)
+    }
+}
+
+/// Binary space partitioning: the map is split in two, and each half again, `bsp_depth`
+/// times or until the halves get too small for a room. Every part that's left gets a room,
+/// and the two halves of every split are joined where their rooms are closest, so nothing is
+/// cut off and no big stretch of the map stays empty.
+pub struct Bsp;
+
+impl MapGenerator for Bsp {
+    fn generate(&self, config: &Config, rng: &mut StdRng) -> Layout {
+        let mut map = walls(config);
+        let mut rooms = vec![];
+
+        // the last row and column stay wall, like the edges of every room
+        let whole = Rect::new(0, 0, config.map_width - 1, config.map_height - 1);
+        split(whole, config.bsp_depth, config, rng, &mut map, &mut rooms);
+
+        Layout::from_rooms(map, &rooms)
+    }
+}
+
+/// Natural caves grown by a cellular automaton: the map starts as random noise of walls and
+/// floors, and each step turns tiles mostly surrounded by walls into wall and the rest into
+/// floor, until it settles into smooth caverns. The caverns that are cut off from the biggest
+/// one are then tunneled to it, or filled in if `cave_tunnels` is off. The player starts
+/// somewhere at random and the stairs are as far away as it gets.
+pub struct Caves;
+
+impl MapGenerator for Caves {
+    fn generate(&self, config: &Config, rng: &mut StdRng) -> Layout {
+        let (width, height) = (config.map_width, config.map_height);
+        let mut map = walls(config);
+
+        // random noise inside the border, which always stays wall
+        for x in 1..width - 1 {
+            for y in 1..height - 1 {
+                if rng.gen_range(0, 100) >= config.cave_wall_chance {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+
+        for _ in 0..config.cave_steps {
+            let mut next = map.clone();
+            for x in 1..width - 1 {
+                for y in 1..height - 1 {
+                    match walls_around(x, y, &map) {
+                        5.. => next[x as usize][y as usize] = Tile::wall(),
+                        ..=3 => next[x as usize][y as usize] = Tile::empty(),
+                        _ => {}
+                    }
+                }
+            }
+            map = next;
+        }
+
+        join_areas(&mut map, config.cave_tunnels, rng);
+        // even the most crowded noise leaves somewhere to stand
+        if connected_areas(&map).is_empty() {
+            map[(width / 2) as usize][(height / 2) as usize] = Tile::empty();
+        }
+
+        let floor = connected_areas(&map).remove(0);
+        let start = floor[rng.gen_range(0, floor.len())];
+
+        Layout {
+            regions: open_regions(&map, config.spawn_regions as usize),
+            stairs: farthest_from(start, &map),
+            map,
+            start,
+        }
+    }
+}
+
+/// A drunkard stumbling about from the middle of the map, digging out every tile they step
+/// on, until `drunkard_coverage` percent of the map is floor. Each drunkard gives up after a
+/// while and the next one sets off from somewhere already dug, which gives winding passages
+/// opening into wider caverns, all of them connected. The stairs are as far from the start as
+/// it gets.
+pub struct DrunkardsWalk;
+
+impl MapGenerator for DrunkardsWalk {
+    fn generate(&self, config: &Config, rng: &mut StdRng) -> Layout {
+        let (width, height) = (config.map_width, config.map_height);
+        let mut map = walls(config);
+
+        let start = (width / 2, height / 2);
+        map[start.0 as usize][start.1 as usize] = Tile::empty();
+        let mut dug = vec![start];
+        // the border always stays wall
+        let target = cmp::max(
+            (width - 2) * (height - 2) * config.drunkard_coverage / 100,
+            1,
+        );
+
+        while (dug.len() as i32) < target {
+            let (mut x, mut y) = dug[rng.gen_range(0, dug.len())];
+            for _ in 0..DRUNKARD_STEPS {
+                let (dx, dy) = [(0, -1), (0, 1), (-1, 0), (1, 0)][rng.gen_range(0, 4)];
+                if x + dx < 1 || x + dx > width - 2 || y + dy < 1 || y + dy > height - 2 {
+                    continue;
+                }
+                x += dx;
+                y += dy;
+                if map[x as usize][y as usize].blocked {
+                    map[x as usize][y as usize] = Tile::empty();
+                    dug.push((x, y));
+                }
+            }
+        }
+
+        Layout {
+            regions: open_regions(&map, config.spawn_regions as usize),
+            stairs: farthest_from(start, &map),
+            map,
+            start,
+        }
+    }
+}
+
+/// how far a drunkard gets before the next one takes over
+const DRUNKARD_STEPS: i32 = 200;
+
+/// Rooms and corridors on one side of the map and caves on the other, split somewhere in the
+/// middle third. The player starts in the first room and the stairs are as far away as it
+/// gets, likely deep in the caves; a corridor joins the two sides where they come closest.
+pub struct Mixed;
+
+impl MapGenerator for Mixed {
+    fn generate(&self, config: &Config, rng: &mut StdRng) -> Layout {
+        let width = config.map_width;
+        let seam = rng.gen_range(width / 3, width - width / 3 + 1);
+
+        // each side laid out as a map of its own, the rooms on the left or the right
+        let rooms_on_left = rng.gen();
+        let (rooms_width, rooms_x, caves_x) = if rooms_on_left {
+            (seam, 0, seam)
+        } else {
+            (width - seam, seam, 0)
+        };
+        let rooms = RandomRooms.generate(
+            &Config {
+                map_width: rooms_width,
+                ..config.clone()
+            },
+            rng,
+        );
+        // as many regions in the caves as rooms would have fit there
+        let caves_width = width - rooms_width;
+        let caves = Caves.generate(
+            &Config {
+                map_width: caves_width,
+                spawn_regions: cmp::max(config.spawn_regions * caves_width / width, 1),
+                ..config.clone()
+            },
+            rng,
+        );
+
+        let mut map = walls(config);
+        let mut regions = vec![];
+        for (side, offset) in [(&rooms, rooms_x), (&caves, caves_x)] {
+            for (x, column) in side.map.iter().enumerate() {
+                map[x + offset as usize] = column.clone();
+            }
+            regions.extend(side.regions.iter().map(|region| {
+                region
+                    .iter()
+                    .map(|&(x, y)| (x + offset, y))
+                    .collect::<Region>()
+            }));
+        }
+
+        // the seam between them
+        join_areas(&mut map, true, rng);
+
+        let start = (rooms.start.0 + rooms_x, rooms.start.1);
+        Layout {
+            stairs: farthest_from(start, &map),
+            map,
+            start,
+            regions,
+        }
+    }
+}
+
+/// the open tile that takes the longest to walk to from the start
+fn farthest_from(start: (i32, i32), map: &Map) -> (i32, i32) {
+    let from_start = DijkstraMap::new(map, &[start]);
+    let mut farthest = (start, 0.0);
+    for (x, column) in map.iter().enumerate() {
+        for y in 0..column.len() {
+            let distance = from_start.value(x as i32, y as i32);
+            if distance.is_finite() && distance > farthest.1 {
+                farthest = ((x as i32, y as i32), distance);
+            }
+        }
+    }
+    farthest.0
+}
+
+/// how many of the eight tiles around are wall, the outside of the map counting as wall
+fn walls_around(x: i32, y: i32, map: &Map) -> usize {
+    let mut count = 0;
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            let (nx, ny) = (x + dx, y + dy);
+            let wall = map
+                .get(nx as usize)
+                .and_then(|column| column.get(ny as usize))
+                .is_none_or(|tile| tile.blocked);
+            if (dx, dy) != (0, 0) && wall {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// every group of open tiles that can be walked between, biggest first; the same steps count
+/// as for moving, so two areas touching only at the corners of walls are apart
+pub fn connected_areas(map: &Map) -> Vec<Region> {
+    let (width, height) = (map.len(), map.first().map_or(0, |column| column.len()));
+    let mut seen = vec![vec![false; height]; width];
+    let mut areas: Vec<Region> = vec![];
+
+    for x in 0..width {
+        for y in 0..height {
+            if seen[x][y] || map[x][y].blocked {
+                continue;
+            }
+            seen[x][y] = true;
+            let mut area = vec![];
+            let mut queue = VecDeque::from([(x as i32, y as i32)]);
+            while let Some((x, y)) = queue.pop_front() {
+                area.push((x, y));
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        let (nx, ny) = (x + dx, y + dy);
+                        let inside =
+                            nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height;
+                        if inside && !seen[nx as usize][ny as usize] && can_step(x, y, dx, dy, map)
+                        {
+                            seen[nx as usize][ny as usize] = true;
+                            queue.push_back((nx, ny));
+                        }
+                    }
+                }
+            }
+            areas.push(area);
+        }
+    }
+
+    areas.sort_by_key(|area| cmp::Reverse(area.len()));
+    areas
+}
+
+/// make every open tile reachable from the biggest area: by digging a corridor from each of
+/// the others to it, or by walling them up
+pub fn join_areas(map: &mut Map, tunnel: bool, rng: &mut StdRng) {
+    let mut areas = connected_areas(map).into_iter();
+    let mut main = match areas.next() {
+        Some(main) => main,
+        None => return,
+    };
+
+    for area in areas {
+        if tunnel {
+            // from wherever the two come closest
+            let distance =
+                |(ax, ay): (i32, i32), (bx, by): (i32, i32)| (ax - bx).pow(2) + (ay - by).pow(2);
+            let closest = area
+                .iter()
+                .flat_map(|&a| main.iter().map(move |&b| (a, b)))
+                .min_by_key(|&(a, b)| distance(a, b));
+            if let Some((a, b)) = closest {
+                connect(a, b, map, rng);
+            }
+            main.extend(area);
+        } else {
+            for (x, y) in area {
+                map[x as usize][y as usize] = Tile::wall();
+            }
+        }
+    }
+}
+
+/// the open tiles cut into squares, leaving out the squares with little floor; the squares
+/// are as small as they can be while there are no more than `count` of them, so a level gets
+/// about as many as a level of rooms has rooms however much floor it has
+pub fn open_regions(map: &Map, count: usize) -> Vec<Region> {
+    let (width, height) = (
+        map.len() as i32,
+        map.first().map_or(0, |column| column.len()) as i32,
+    );
+
+    let mut size = 2;
+    loop {
+        let regions = squares(map, size);
+        if regions.len() <= count || size >= cmp::max(width, height) {
+            return regions;
+        }
+        size += 1;
+    }
+}
+
+/// the open tiles of every `size` square with at least a quarter of it open
+fn squares(map: &Map, size: i32) -> Vec<Region> {
+    let (width, height) = (
+        map.len() as i32,
+        map.first().map_or(0, |column| column.len()) as i32,
+    );
+    let mut regions = vec![];
+
+    for left in (0..width).step_by(size as usize) {
+        for top in (0..height).step_by(size as usize) {
+            let region: Region = (left..cmp::min(left + size, width))
+                .flat_map(|x| (top..cmp::min(top + size, height)).map(move |y| (x, y)))
+                .filter(|&(x, y)| !map[x as usize][y as usize].blocked)
+                .collect();
+            if region.len() as i32 >= size * size / 4 {
+                regions.push(region);
+            }
+        }
+    }
+
+    regions
+}
+
+/// split the area and its halves until `depth` runs out, then put a room in each part
+fn split(
+    area: Rect,
+    depth: i32,
+    config: &Config,
+    rng: &mut StdRng,
+    map: &mut Map,
+    rooms: &mut Vec<Rect>,
+) {
+    let (width, height) = (area.x2 - area.x1, area.y2 - area.y1);
+    // both halves have to fit the smallest room
+    let min = config.room_min_size;
+    let across = width >= 2 * min;
+    let down = height >= 2 * min;
+
+    if depth == 0 || (!across && !down) {
+        let room = room_in(area, config, rng);
+        create_room(room, map);
+        rooms.push(room);
+        return;
+    }
+
+    // cut the longer side, so the parts don't end up as thin strips
+    let vertical = match (across, down) {
+        (true, true) if width == height => rng.gen(),
+        (true, true) => width > height,
+        (across, _) => across,
+    };
+    let (first, second) = if vertical {
+        let x = rng.gen_range(area.x1 + min, area.x2 - min + 1);
+        (
+            Rect::new(area.x1, area.y1, x - area.x1, height),
+            Rect::new(x, area.y1, area.x2 - x, height),
+        )
+    } else {
+        let y = rng.gen_range(area.y1 + min, area.y2 - min + 1);
+        (
+            Rect::new(area.x1, area.y1, width, y - area.y1),
+            Rect::new(area.x1, y, width, area.y2 - y),
+        )
+    };
+
+    let start = rooms.len();
+    split(first, depth - 1, config, rng, map, rooms);
+    let middle = rooms.len();
+    split(second, depth - 1, config, rng, map, rooms);
+
+    // join the halves by the closest two rooms either side
+    let distance = |a: &Rect, b: &Rect| {
+        let ((ax, ay), (bx, by)) = (a.center(), b.center());
+        (ax - bx).pow(2) + (ay - by).pow(2)
+    };
+    let closest = rooms[start..middle]
+        .iter()
+        .flat_map(|a| rooms[middle..].iter().map(move |b| (a, b)))
+        .min_by_key(|(a, b)| distance(a, b));
+    if let Some((a, b)) = closest {
+        connect(a.center(), b.center(), map, rng);
+    }
+}
+
+/// a room of random size somewhere inside the area
+fn room_in(area: Rect, config: &Config, rng: &mut StdRng) -> Rect {
+    let max_w = cmp::min(config.room_max_size, area.x2 - area.x1);
+    let max_h = cmp::min(config.room_max_size, area.y2 - area.y1);
+    let w = rng.gen_range(config.room_min_size, max_w + 1);
+    let h = rng.gen_range(config.room_min_size, max_h + 1);
+    let x = rng.gen_range(area.x1, area.x2 - w + 1);
+    let y = rng.gen_range(area.y1, area.y2 - h + 1);
+    Rect::new(x, y, w, h)
+}
+
+/// a map of nothing but "blocked" tiles
+fn walls(config: &Config) -> Map {
+    vec![vec![Tile::wall(); config.map_height as usize]; config.map_width as usize]
+}
+
+fn create_room(room: Rect, map: &mut Map) {
+    // go through the tiles in the rectangle and make them passable
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+/// dig an L-shaped corridor between the two points
+fn connect((x1, y1): (i32, i32), (x2, y2): (i32, i32), map: &mut Map, rng: &mut StdRng) {
+    // flip a coin
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_horizontal_tunnel(x1, x2, y1, map);
+        create_vertical_tunnel(y1, y2, x2, map);
+    } else {
+        // first move vertically, then horizontally
+        create_vertical_tunnel(y1, y2, x1, map);
+        create_horizontal_tunnel(x1, x2, y2, map);
+    }
+}
+
+fn create_horizontal_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_vertical_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CONFIG_PATH;
+    use rand::SeedableRng;
+
+    /// the start, the stairs and every region are on floor, and all of it can be walked to
+    /// from the start
+    fn assert_playable(layout: &Layout) {
+        let open = |&(x, y): &(i32, i32)| !layout.map[x as usize][y as usize].blocked;
+        assert!(open(&layout.start) && open(&layout.stairs));
+        assert!(layout.regions.iter().flatten().all(open));
+        let areas = connected_areas(&layout.map);
+        assert_eq!(areas.len(), 1);
+        assert!(areas[0].contains(&layout.start));
+    }
+
+    #[test]
+    fn levels_of_rooms_are_walled_in_and_connected() {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        let (width, height) = (config.map_width, config.map_height);
+        let generators: [&dyn MapGenerator; 2] = [&RandomRooms, &Bsp];
+        for generator in generators {
+            for seed in 0..20 {
+                let layout = generator.generate(&config, &mut StdRng::seed_from_u64(seed));
+                assert!(!layout.regions.is_empty());
+                assert_playable(&layout);
+
+                // every room keeps off the edge of the map, which stays wall all around
+                let inside =
+                    |&(x, y): &(i32, i32)| x > 0 && y > 0 && x < width - 1 && y < height - 1;
+                assert!(layout.regions.iter().flatten().all(inside));
+                for x in 0..width {
+                    for y in [0, height - 1] {
+                        assert!(layout.map[x as usize][y as usize].blocked);
+                    }
+                }
+                for y in 0..height {
+                    for x in [0, width - 1] {
+                        assert!(layout.map[x as usize][y as usize].blocked);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn open_regions_are_no_more_than_asked_for() {
+        let mut map = vec![vec![Tile::empty(); 43]; 80];
+        assert_eq!(open_regions(&map, 12).len(), 12);
+        assert_eq!(open_regions(&map, 1).len(), 1);
+
+        // a single tile of floor makes no region once the squares are big
+        for column in map.iter_mut() {
+            for tile in column.iter_mut() {
+                *tile = Tile::wall();
+            }
+        }
+        map[5][5] = Tile::empty();
+        assert_eq!(open_regions(&map, 100), vec![vec![(5, 5)]]);
+        assert!(open_regions(&map, 0).is_empty());
+    }
+
+    #[test]
+    fn levels_without_rooms_get_as_many_regions_as_rooms_would() {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        // the rooms of mixed levels are counted like those of a level of rooms
+        let most = config.spawn_regions;
+        let generators: [(&dyn MapGenerator, i32); 3] = [
+            (&Caves, most),
+            (&DrunkardsWalk, most),
+            (&Mixed, most * 3 / 2),
+        ];
+        for (generator, most) in generators {
+            for seed in 0..20 {
+                let layout = generator.generate(&config, &mut StdRng::seed_from_u64(seed));
+                let regions = layout.regions.len() as i32;
+                assert!(regions > 0 && regions <= most, "{} regions", regions);
+                assert_playable(&layout);
+            }
+        }
+    }
+}
@@ -1,92 +1,340 @@
-use crate::domain::Object;
-use crate::{INVENTORY_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
-use tcod::colors::*;
-use tcod::console::{Offscreen, Root};
-use tcod::{BackgroundFlag, Console, TextAlignment};
+use crate::domain;
+use crate::render::{numpad_digit, starts_text, wrap_text, Renderer};
+use crate::world::{Entity, World};
+use std::cmp;
+use std::mem;
+use tcod::colors::{self, *};
+use tcod::input::{Event, KeyCode};
+use tcod::TextAlignment;
 
-const MAX_MENU_OPTIONS: usize = 26;
+/// the hotkeys of the rows of a page, top to bottom
+const HOTKEYS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const HIGHLIGHT_COLOR: Color = DARKER_AZURE;
 
+/// Show the options under the header and let the player pick one: with the hotkey next to it,
+/// by moving the highlight with the arrow keys or the numpad and pressing Enter, or by
+/// clicking it. Lists too long for the screen are split into pages, flipped with PageUp and
+/// PageDown (9 and 3 on the numpad) or by moving past the first or last row. Any other key
+/// or a right-click cancels. Without any options it's a message box that any key or click
+/// closes.
 pub fn menu<T: AsRef<str>>(
     header: &str,
     options: &[T],
     width: i32,
-    root: &mut Root,
+    renderer: &mut impl Renderer,
 ) -> Option<usize> {
-    assert!(
-        options.len() <= MAX_MENU_OPTIONS,
-        "Cannot have a menu with more than {} options.",
-        MAX_MENU_OPTIONS
-    );
-
-    // calculate total height for the header (after auto-wrap) and one line per option
-    let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
-    let height = options.len() as i32 + header_height;
-
-    // create an off-screen console that represents the menu's window
-    let mut window = Offscreen::new(width, height);
-
-    // print the header with auto-wrap
-    window.set_default_foreground(WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
-    );
-
-    // print all the options
-    for (index, option_text) in options.iter().enumerate() {
-        let menu_letter = (b'a' + index as u8) as char;
-        let text = format!("({}) {}", menu_letter, option_text.as_ref());
-
-        window.print_ex(
-            0,
-            header_height + index as i32,
-            BackgroundFlag::None,
-            TextAlignment::Left,
-            text,
-        );
+    // calculate total height for the header (after auto-wrap)
+    let header_height = if header.is_empty() {
+        0
+    } else {
+        wrap_text(header, width).len() as i32
+    };
+    let (screen_width, screen_height) = renderer.size();
+
+    // as many options per page as fit below the header and have a hotkey, keeping a row to
+    // tell which page it is when there's more than one
+    let fits = cmp::max(screen_height - header_height, 2) as usize;
+    let mut page_size = options.len().min(fits).min(HOTKEYS.len());
+    if page_size < options.len() {
+        page_size = (fits - 1).min(HOTKEYS.len());
     }
+    let page_size = cmp::max(page_size, 1);
+    let pages = options.len().div_ceil(page_size);
+    let footer_height = if pages > 1 { 1 } else { 0 };
+    let height = header_height + cmp::min(options.len(), page_size) as i32 + footer_height;
+
+    // center the menu's window on the screen
+    let x = screen_width / 2 - width / 2;
+    let y = screen_height / 2 - height / 2;
 
-    // blit the contents to the root console
-    let x = SCREEN_WIDTH / 2 - width / 2;
-    let y = SCREEN_HEIGHT / 2 - height / 2;
-    tcod::console::blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
-
-    // present the root console to the player and wait for a keypress
-    root.flush();
-    let key = root.wait_for_keypress(true);
-
-    // convert the ASCII code to an index; if it corresponds to an option, return it
-    if key.printable.is_alphabetic() {
-        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
-            Some(index)
-        } else {
-            None
+    // let a little of the game show through the window's background; worked out once, so
+    // drawing the window again doesn't darken it further
+    let mut backdrop = vec![];
+    for window_y in y..y + height {
+        for window_x in x..x + width {
+            if renderer.in_bounds(window_x, window_y) {
+                let background = colors::lerp(renderer.background(window_x, window_y), BLACK, 0.7);
+                backdrop.push((window_x, window_y, background));
+            }
+        }
+    }
+
+    let mut selected = 0;
+    let mut numpad_pressed = false;
+    loop {
+        let page_start = selected / page_size * page_size;
+        let page_end = cmp::min(page_start + page_size, options.len());
+
+        // blank out the window and print the header with auto-wrap
+        for &(window_x, window_y, background) in &backdrop {
+            renderer.put_char(window_x, window_y, ' ', WHITE);
+            renderer.set_background(window_x, window_y, background);
+        }
+        renderer.print_rect(x, y, width, header, WHITE);
+
+        // print the options of the page, the selected one highlighted
+        for (row, index) in (page_start..page_end).enumerate() {
+            let row_y = y + header_height + row as i32;
+            if index == selected {
+                renderer.fill_background(x, row_y, width, 1, HIGHLIGHT_COLOR);
+            }
+            let hotkey = HOTKEYS.as_bytes()[row] as char;
+            let text = format!("({}) {}", hotkey, options[index].as_ref());
+            renderer.print(x, row_y, &text, WHITE, TextAlignment::Left);
+        }
+        if pages > 1 {
+            let text = format!(
+                "page {} of {}, PageUp/PageDown to turn",
+                page_start / page_size + 1,
+                pages
+            );
+            renderer.print(x, y + height - 1, &text, LIGHT_GREY, TextAlignment::Left);
+        }
+
+        // present the screen to the player and wait for them to do something
+        renderer.flush();
+        let last = options.len().saturating_sub(1);
+        match renderer.wait_for_event() {
+            Event::Key(key) => {
+                // the digit a numpad key types isn't the hotkey of a row
+                let after_numpad = mem::replace(&mut numpad_pressed, numpad_digit(key));
+                match key.code {
+                    _ if starts_text(key) => {}
+                    KeyCode::Text if after_numpad => {}
+                    _ if options.is_empty() => return None,
+                    KeyCode::Up | KeyCode::NumPad8 => {
+                        selected = if selected == 0 { last } else { selected - 1 }
+                    }
+                    KeyCode::Down | KeyCode::NumPad2 => {
+                        selected = if selected == last { 0 } else { selected + 1 }
+                    }
+                    KeyCode::PageUp | KeyCode::NumPad9 => {
+                        selected = selected.saturating_sub(page_size)
+                    }
+                    KeyCode::PageDown | KeyCode::NumPad3 => {
+                        selected = cmp::min(selected + page_size, last)
+                    }
+                    KeyCode::Home | KeyCode::NumPad7 => selected = 0,
+                    KeyCode::End | KeyCode::NumPad1 => selected = last,
+                    KeyCode::Enter | KeyCode::NumPadEnter => return Some(selected),
+                    KeyCode::Text => {
+                        // the hotkeys stand for the rows of the page on display
+                        let row = key.text().chars().next().and_then(|c| HOTKEYS.find(c));
+                        return row
+                            .map(|row| page_start + row)
+                            .filter(|&index| index < page_end);
+                    }
+                    _ => return None,
+                }
+            }
+            Event::Mouse(mouse) => {
+                if mouse.rbutton_pressed || (options.is_empty() && mouse.lbutton_pressed) {
+                    return None;
+                }
+                if mouse.wheel_up {
+                    selected = selected.saturating_sub(1);
+                    continue;
+                } else if mouse.wheel_down {
+                    selected = cmp::min(selected + 1, last);
+                    continue;
+                }
+
+                // the highlight follows the mouse, a click picks the row under it
+                let (mouse_x, mouse_y) = (mouse.cx as i32, mouse.cy as i32);
+                let row = mouse_y - y - header_height;
+                let index = page_start as i32 + row;
+                if mouse_x >= x && mouse_x < x + width && row >= 0 && index < page_end as i32 {
+                    selected = index as usize;
+                    if mouse.lbutton_pressed {
+                        return Some(selected);
+                    }
+                } else if mouse.lbutton_pressed {
+                    // clicking outside the window cancels
+                    return None;
+                }
+            }
         }
-    } else {
-        None
     }
 }
 
-pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
+/// let the player pick one of the items the entity carries
+pub fn inventory_menu(
+    owner: Entity,
+    world: &World,
+    header: &str,
+    width: i32,
+    renderer: &mut impl Renderer,
+) -> Option<Entity> {
+    let inventory = world.inventory(owner);
+
     // show a menu with each item of the inventory as an option
     let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
-        inventory.iter().map(|item| item.name.clone()).collect()
+        inventory
+            .iter()
+            .map(|&item| {
+                // show additional information, in case it's equipped
+                match world.equipment.get(item) {
+                    Some(equipment) if equipment.equipped => {
+                        format!("{} (on {})", world.name(item), equipment.slot)
+                    }
+                    _ => domain::item_label(item, world),
+                }
+            })
+            .collect()
     };
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+    let inventory_index = menu(header, &options, width, renderer);
 
     // if an item was chosen, return it
-    if !inventory.is_empty() {
-        inventory_index
-    } else {
-        None
+    inventory_index.and_then(|index| inventory.get(index).copied())
+}
+
+/// show a message and wait for any key
+pub fn msgbox(text: &str, width: i32, renderer: &mut impl Renderer) {
+    let options: &[&str] = &[];
+    menu(text, options, width, renderer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Inventory;
+    use crate::render::{scripted, GridRenderer};
+
+    const WIDTH: i32 = 30;
+
+    /// a player carrying the named items
+    fn carrying(names: &[&str]) -> World {
+        let mut world = World::new();
+        world.player = world.spawn();
+        world.inventories.insert(world.player, Inventory::default());
+        for name in names {
+            let item = world.spawn();
+            world.names.insert(item, name.to_string());
+            world
+                .inventories
+                .get_mut(world.player)
+                .unwrap()
+                .items
+                .push(item);
+        }
+        world
+    }
+
+    fn choose(world: &World, events: Vec<Event>) -> (Option<Entity>, GridRenderer) {
+        let mut grid = GridRenderer::new(40, 20);
+        grid.events.extend(events);
+        let choice = inventory_menu(world.player, world, "Pick one", WIDTH, &mut grid);
+        (choice, grid)
+    }
+
+    fn row(grid: &GridRenderer, y: i32) -> String {
+        grid.snapshot().lines().nth(y as usize).unwrap().to_string()
+    }
+
+    #[test]
+    fn the_inventory_menu_lists_the_items() {
+        let world = carrying(&["sword", "scroll of fireball"]);
+        let (choice, grid) = choose(&world, vec![scripted::key(KeyCode::Escape)]);
+        assert_eq!(choice, None);
+
+        // centered on the 40x20 screen: the header and two rows, from row 9 and column 5
+        assert_eq!(row(&grid, 9).trim_end(), "     Pick one");
+        assert_eq!(row(&grid, 10).trim_end(), "     (a) sword");
+        assert_eq!(row(&grid, 11).trim_end(), "     (b) scroll of fireball");
+        assert_eq!(grid.cell(5, 10).background, HIGHLIGHT_COLOR);
+        assert_ne!(grid.cell(5, 11).background, HIGHLIGHT_COLOR);
+    }
+
+    #[test]
+    fn the_inventory_menu_returns_the_chosen_item() {
+        let world = carrying(&["sword", "shield", "helmet"]);
+        let items = world.inventory(world.player).to_vec();
+
+        let (choice, _) = choose(&world, scripted::typed("b"));
+        assert_eq!(choice, Some(items[1]));
+
+        let events = vec![
+            scripted::key(KeyCode::Down),
+            scripted::key(KeyCode::Down),
+            scripted::key(KeyCode::Enter),
+        ];
+        let (choice, grid) = choose(&world, events);
+        assert_eq!(choice, Some(items[2]));
+        assert_eq!(grid.cell(5, 11).background, HIGHLIGHT_COLOR);
+
+        let (choice, _) = choose(&world, vec![scripted::mouse(8, 9, true)]);
+        assert_eq!(choice, Some(items[0]));
+
+        // no such row
+        let (choice, _) = choose(&world, scripted::typed("z"));
+        assert_eq!(choice, None);
+    }
+
+    #[test]
+    fn the_numpad_moves_the_highlight_and_turns_the_pages() {
+        let names: Vec<_> = (0..30).map(|n| format!("potion {}", n)).collect();
+        let world = carrying(&names.iter().map(|name| name.as_str()).collect::<Vec<_>>());
+        let items = world.inventory(world.player).to_vec();
+
+        // with Num Lock on, each key is followed by the digit it types
+        let numpad = |code: KeyCode, digit: char| vec![scripted::key(code), scripted::text(digit)];
+        let mut events = vec![];
+        events.extend(numpad(KeyCode::NumPad3, '3'));
+        events.extend(numpad(KeyCode::NumPad2, '2'));
+        events.extend(numpad(KeyCode::NumPad2, '2'));
+        events.extend(numpad(KeyCode::NumPad8, '8'));
+        let (choice, grid) = choose(&world, events.clone());
+        // the events ran out on the second page, which doesn't count as a choice
+        assert_eq!(choice, None);
+        assert!(row(&grid, 19).trim_start().starts_with("page 2 of 2"));
+
+        events.extend(numpad(KeyCode::NumPad9, '9'));
+        events.push(scripted::key(KeyCode::Enter));
+        let (choice, _) = choose(&world, events);
+        // down to the second page, two rows down and one back up, then a page back
+        assert_eq!(choice, Some(items[1]));
+    }
+
+    #[test]
+    fn a_message_box_closes_on_the_whole_typed_key() {
+        let mut grid = GridRenderer::new(40, 20);
+        grid.events.extend(scripted::typed("i"));
+        msgbox("Hello", WIDTH, &mut grid);
+        // nothing left over for the game to act on
+        assert!(grid.events.is_empty());
+    }
+
+    #[test]
+    fn the_wheel_scrolls_under_the_pointer() {
+        let world = carrying(&["sword", "shield", "helmet"]);
+        let items = world.inventory(world.player).to_vec();
+
+        // the pointer rests on the first row while the wheel turns twice
+        let wheel_down = match scripted::mouse(8, 9, false) {
+            Event::Mouse(mut mouse) => {
+                mouse.wheel_down = true;
+                Event::Mouse(mouse)
+            }
+            event => event,
+        };
+        let events = vec![
+            scripted::mouse(8, 9, false),
+            wheel_down,
+            wheel_down,
+            scripted::key(KeyCode::Enter),
+        ];
+        let (choice, _) = choose(&world, events);
+        assert_eq!(choice, Some(items[2]));
+    }
+
+    #[test]
+    fn an_empty_inventory_says_so() {
+        let world = carrying(&[]);
+        let (choice, grid) = choose(&world, scripted::typed("a"));
+        assert_eq!(choice, None);
+        assert!(row(&grid, 10).contains("(a) Inventory is empty."));
     }
 }
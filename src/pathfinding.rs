@@ -0,0 +1,197 @@
+use crate::domain::{can_step, Map};
+use crate::world::World;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// what stepping onto a tile taken by a blocking entity costs, compared to 1 for a free tile;
+/// high enough to walk around a crowd, low enough to wait in line in a corridor
+const BLOCKED_COST: i32 = 8;
+
+/// A cached route to `target`, recomputed when the target moves or the way gets blocked
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub target: (i32, i32),
+    /// the tiles still to walk, the next one last
+    steps: Vec<(i32, i32)>,
+}
+
+impl Path {
+    pub fn next_step(&self) -> Option<(i32, i32)> {
+        self.steps.last().copied()
+    }
+
+    /// drop the step that was just taken
+    pub fn advance(&mut self) {
+        self.steps.pop();
+    }
+}
+
+/// the cheapest route from `from` to `to` with A*, moving in all eight directions. Walls can't
+/// be crossed, nor their corners cut; tiles with a blocking entity other than at `to` can, at
+/// a higher cost, since whatever stands there is likely to move out of the way.
+pub fn find_path(from: (i32, i32), to: (i32, i32), map: &Map, world: &World) -> Option<Path> {
+    let width = map.len() as i32;
+    let height = map.first().map_or(0, |column| column.len() as i32);
+    let in_map = |(x, y): (i32, i32)| x >= 0 && x < width && y >= 0 && y < height;
+    if !in_map(from) || !in_map(to) {
+        return None;
+    }
+    let index = |(x, y): (i32, i32)| (x * height + y) as usize;
+
+    // look up the blocking entities once instead of for every tile visited
+    let mut occupied = vec![false; (width * height) as usize];
+    for (entity, _) in world.blocks.iter() {
+        if let Some(position) = world.pos(entity).filter(|&position| in_map(position)) {
+            occupied[index(position)] = true;
+        }
+    }
+
+    // every step costs at least 1, so the number of king moves left never overestimates
+    let heuristic = |(x, y): (i32, i32)| (x - to.0).abs().max((y - to.1).abs());
+
+    let mut cost = vec![i32::MAX; occupied.len()];
+    let mut came_from = vec![None; occupied.len()];
+    let mut open = BinaryHeap::new();
+    cost[index(from)] = 0;
+    open.push(Reverse((heuristic(from), from)));
+
+    while let Some(Reverse((_, current))) = open.pop() {
+        if current == to {
+            // walk back from the target, which leaves the next step at the end
+            let mut steps = vec![];
+            let mut tile = to;
+            while tile != from {
+                steps.push(tile);
+                tile = came_from[index(tile)]?;
+            }
+            return Some(Path { target: to, steps });
+        }
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let next = (current.0 + dx, current.1 + dy);
+                if (dx, dy) == (0, 0)
+                    || !in_map(next)
+                    || !can_step(current.0, current.1, dx, dy, map)
+                {
+                    continue;
+                }
+
+                let step_cost = if occupied[index(next)] && next != to {
+                    BLOCKED_COST
+                } else {
+                    1
+                };
+                let next_cost = cost[index(current)] + step_cost;
+                if next_cost < cost[index(next)] {
+                    cost[index(next)] = next_cost;
+                    came_from[index(next)] = Some(current);
+                    open.push(Reverse((next_cost + heuristic(next), next)));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::map_from;
+    use crate::world::World;
+
+    fn world_with_blockers(blockers: &[(i32, i32)]) -> World {
+        let mut world = World::new();
+        for &(x, y) in blockers {
+            let blocker = world.spawn();
+            world.set_pos(blocker, x, y);
+            world.blocks.insert(blocker, ());
+        }
+        world
+    }
+
+    /// the whole route, from the first step to the target
+    fn route(path: &Path) -> Vec<(i32, i32)> {
+        path.steps.iter().rev().copied().collect()
+    }
+
+    #[test]
+    fn diagonals_cost_the_same_as_straight_steps() {
+        let map = map_from(&[
+            "#######", //
+            "#.....#", //
+            "#.....#", //
+            "#.....#", //
+            "#######", //
+        ]);
+        let path = find_path((1, 1), (5, 3), &map, &World::new()).unwrap();
+        assert_eq!(path.target, (5, 3));
+        assert_eq!(route(&path).len(), 4);
+        assert_eq!(route(&path).last(), Some(&(5, 3)));
+        assert_eq!(path.next_step(), Some(route(&path)[0]));
+    }
+
+    #[test]
+    fn walls_are_walked_around_without_cutting_corners() {
+        let map = map_from(&[
+            "#####", //
+            "#.#.#", //
+            "#.#.#", //
+            "#...#", //
+            "#####", //
+        ]);
+        let path = find_path((1, 1), (3, 1), &map, &World::new()).unwrap();
+        assert_eq!(
+            route(&path),
+            vec![(1, 2), (1, 3), (2, 3), (3, 3), (3, 2), (3, 1)]
+        );
+
+        // the only way is between the corners of two walls
+        let map = map_from(&[
+            "####", //
+            "#.##", //
+            "##.#", //
+            "####", //
+        ]);
+        assert!(find_path((1, 1), (2, 2), &map, &World::new()).is_none());
+    }
+
+    #[test]
+    fn blockers_are_avoided_when_possible_and_crossed_when_not() {
+        let room = map_from(&[
+            "#####", //
+            "#...#", //
+            "#...#", //
+            "#...#", //
+            "#####", //
+        ]);
+        let world = world_with_blockers(&[(2, 2)]);
+        let path = find_path((1, 2), (3, 2), &room, &world).unwrap();
+        assert_eq!(route(&path).len(), 2);
+        assert!(!route(&path).contains(&(2, 2)));
+
+        let corridor = map_from(&[
+            "#####", //
+            "#...#", //
+            "#####", //
+        ]);
+        let path = find_path((1, 1), (3, 1), &corridor, &world_with_blockers(&[(2, 1)])).unwrap();
+        assert_eq!(route(&path), vec![(2, 1), (3, 1)]);
+
+        // a blocker on the target itself is what's being chased
+        let path = find_path((1, 1), (3, 1), &corridor, &world_with_blockers(&[(3, 1)])).unwrap();
+        assert_eq!(route(&path), vec![(2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn no_path_out_of_the_map_or_into_a_closed_room() {
+        let map = map_from(&[
+            "#######", //
+            "#..#..#", //
+            "#######", //
+        ]);
+        assert!(find_path((1, 1), (4, 1), &map, &World::new()).is_none());
+        assert!(find_path((1, 1), (9, 1), &map, &World::new()).is_none());
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use tcod::colors::{self, Color, BLACK, WHITE};
+use tcod::console::TextAlignment;
+use tcod::input::{Event, Key, KeyCode};
+
+/// Everything the game needs from a display: a grid of cells with a glyph, a foreground and
+/// a background color, plus a way to wait for the player's input. `Tcod` implements it on top
+/// of the SDL window, `GridRenderer` keeps the screen in memory so it can run without a display.
+pub trait Renderer {
+    /// width and height of the screen, in cells
+    fn size(&self) -> (i32, i32);
+
+    /// blank every cell: no glyph, white foreground, black background
+    fn clear(&mut self);
+
+    fn background(&self, x: i32, y: i32) -> Color;
+
+    fn set_background(&mut self, x: i32, y: i32, color: Color);
+
+    /// draw a glyph in the given color, keeping the cell's background
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, color: Color);
+
+    /// present the finished frame to the player
+    fn flush(&mut self);
+
+    /// block until the player presses a key or does something with the mouse
+    fn wait_for_event(&mut self) -> Event;
+
+    /// whether the player asked to quit by closing the window; waiting for input then only
+    /// gets keys that back out of everything
+    fn window_closed(&self) -> bool;
+
+    fn in_bounds(&self, x: i32, y: i32) -> bool {
+        let (width, height) = self.size();
+        x >= 0 && x < width && y >= 0 && y < height
+    }
+
+    /// mix the color into the cell's background, `alpha` being the weight of the new color
+    fn blend_background(&mut self, x: i32, y: i32, color: Color, alpha: f32) {
+        if self.in_bounds(x, y) {
+            let blended = colors::lerp(self.background(x, y), color, alpha);
+            self.set_background(x, y, blended);
+        }
+    }
+
+    /// set the background of every cell in the rectangle
+    fn fill_background(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        for cell_y in y..y + height {
+            for cell_x in x..x + width {
+                if self.in_bounds(cell_x, cell_y) {
+                    self.set_background(cell_x, cell_y, color);
+                }
+            }
+        }
+    }
+
+    /// print a single line of text, clipped to the screen
+    fn print(&mut self, x: i32, y: i32, text: &str, color: Color, alignment: TextAlignment) {
+        let length = text.chars().count() as i32;
+        let start_x = match alignment {
+            TextAlignment::Left => x,
+            TextAlignment::Right => x - length + 1,
+            TextAlignment::Center => x - length / 2,
+        };
+
+        for (offset, glyph) in text.chars().enumerate() {
+            let cell_x = start_x + offset as i32;
+            if self.in_bounds(cell_x, y) {
+                self.put_char(cell_x, y, glyph, color);
+            }
+        }
+    }
+
+    /// print text wrapped to the given width and return how many lines it took
+    fn print_rect(&mut self, x: i32, y: i32, width: i32, text: &str, color: Color) -> i32 {
+        let lines = wrap_text(text, width);
+        for (offset, line) in lines.iter().enumerate() {
+            self.print(x, y + offset as i32, line, color, TextAlignment::Left);
+        }
+        lines.len() as i32
+    }
+}
+
+/// whether the key only starts typing a character: tcod reports a typed character as a key
+/// press followed by a text event with the character, and only the text tells what was
+/// typed on the player's layout. Screens that read text skip these keys and wait for it.
+pub fn starts_text(key: Key) -> bool {
+    matches!(
+        key.code,
+        KeyCode::Shift
+            | KeyCode::Char
+            | KeyCode::Number0
+            | KeyCode::Number1
+            | KeyCode::Number2
+            | KeyCode::Number3
+            | KeyCode::Number4
+            | KeyCode::Number5
+            | KeyCode::Number6
+            | KeyCode::Number7
+            | KeyCode::Number8
+            | KeyCode::Number9
+    )
+}
+
+/// whether the key is a digit on the numpad; with Num Lock on, tcod follows it with the digit
+/// as text, which screens that act on the key itself must not take for a typed character
+pub fn numpad_digit(key: Key) -> bool {
+    matches!(
+        key.code,
+        KeyCode::NumPad0
+            | KeyCode::NumPad1
+            | KeyCode::NumPad2
+            | KeyCode::NumPad3
+            | KeyCode::NumPad4
+            | KeyCode::NumPad5
+            | KeyCode::NumPad6
+            | KeyCode::NumPad7
+            | KeyCode::NumPad8
+            | KeyCode::NumPad9
+    )
+}
+
+/// split text into lines no wider than `width`, breaking on spaces where possible
+/// and honouring explicit newlines
+pub fn wrap_text(text: &str, width: i32) -> Vec<String> {
+    let width = width.max(1) as usize;
+    let mut lines = vec![];
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+
+        for word in paragraph.split(' ') {
+            let line_length = line.chars().count();
+            let word_length = word.chars().count();
+
+            if line_length > 0 && line_length + 1 + word_length > width {
+                lines.push(line);
+                line = String::new();
+            } else if line_length > 0 {
+                line.push(' ');
+            }
+
+            // words longer than a whole line get cut wherever they overflow
+            for glyph in word.chars() {
+                if line.chars().count() == width {
+                    lines.push(line);
+                    line = String::new();
+                }
+                line.push(glyph);
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// A single character cell of the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub glyph: char,
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        Cell {
+            glyph: ' ',
+            foreground: WHITE,
+            background: BLACK,
+        }
+    }
+}
+
+/// An in-memory screen that records what would have been drawn, so the UI can be rendered
+/// and inspected without opening a window.
+pub struct GridRenderer {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    /// input handed out, in order, whenever the game waits for some
+    pub events: VecDeque<Event>,
+}
+
+impl GridRenderer {
+    pub fn new(width: i32, height: i32) -> Self {
+        GridRenderer {
+            width,
+            height,
+            cells: vec![Cell::blank(); (width * height) as usize],
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> Cell {
+        self.cells[(y * self.width + x) as usize]
+    }
+
+    /// the glyphs of the whole screen, one line of text per row
+    pub fn snapshot(&self) -> String {
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| self.cell(x, y).glyph)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell {
+        &mut self.cells[(y * self.width + x) as usize]
+    }
+}
+
+impl Renderer for GridRenderer {
+    fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = Cell::blank();
+        }
+    }
+
+    fn background(&self, x: i32, y: i32) -> Color {
+        self.cell(x, y).background
+    }
+
+    fn set_background(&mut self, x: i32, y: i32, color: Color) {
+        self.cell_mut(x, y).background = color;
+    }
+
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, color: Color) {
+        let cell = self.cell_mut(x, y);
+        cell.glyph = glyph;
+        cell.foreground = color;
+    }
+
+    fn flush(&mut self) {
+        // nothing to present, the cells are the output
+    }
+
+    fn window_closed(&self) -> bool {
+        // once the script is over, like a player who left
+        self.events.is_empty()
+    }
+
+    fn wait_for_event(&mut self) -> Event {
+        // running out of scripted input reads as a key no menu understands, ie. cancel
+        self.events
+            .pop_front()
+            .unwrap_or_else(|| Event::Key(Key::default()))
+    }
+}
+
+/// Input for `GridRenderer::events`, the way tcod would report it
+#[cfg(test)]
+pub mod scripted {
+    use tcod::input::{Event, Key, KeyCode, Mouse};
+    use tcod_sys::{TCOD_key_t, TCOD_keycode_t};
+
+    /// a key without any text, like the arrows or Enter
+    pub fn key(code: KeyCode) -> Event {
+        // the text is private, so no struct literal
+        let mut key = Key::default();
+        key.code = code;
+        key.pressed = true;
+        Event::Key(key)
+    }
+
+    /// the events of typing the text: each character comes as a key followed by its text
+    pub fn typed(text: &str) -> Vec<Event> {
+        text.chars()
+            .flat_map(|glyph| vec![key(KeyCode::Char), self::text(glyph)])
+            .collect()
+    }
+
+    /// the text event that follows the key of a typed character
+    pub fn text(glyph: char) -> Event {
+        let mut native: TCOD_key_t = unsafe { std::mem::zeroed() };
+        native.vk = TCOD_keycode_t::TCODK_TEXT;
+        native.c = glyph as _;
+        native.pressed = 1;
+        let mut buffer = [0; 4];
+        let bytes = glyph.encode_utf8(&mut buffer).as_bytes();
+        for (to, &from) in native.text.iter_mut().zip(bytes) {
+            *to = from as _;
+        }
+        Event::Key(Key::from(native))
+    }
+
+    /// the mouse over the cell, clicking it with the left button if `click`
+    pub fn mouse(x: i32, y: i32, click: bool) -> Event {
+        Event::Mouse(Mouse {
+            cx: x as isize,
+            cy: y as isize,
+            lbutton_pressed: click,
+            ..Mouse::default()
+        })
+    }
+}
@@ -0,0 +1,811 @@
+use crate::domain::{
+    Ai, DeathCallback, Equipment, Fighter, Game, Inventory, Item, Message, Messages, Position,
+    Renderable, Slot, Stack, Tile,
+};
+use crate::scheduler::{Actor, Boost};
+use crate::world::{Components, Entity, World};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use tcod::colors::Color;
+
+/// where the game is saved to and continued from
+pub const SAVE_PATH: &str = "savegame";
+
+// bump this whenever the layout below changes, older saves are then refused
+const SAVE_MAGIC: &str = "untitled-adventures-save";
+const SAVE_VERSION: u32 = 10;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Version(u32),
+    Malformed(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "could not read the save file: {}", error),
+            LoadError::Version(version) => write!(
+                f,
+                "the save file is from version {}, only version {} can be loaded",
+                version, SAVE_VERSION
+            ),
+            LoadError::Malformed(reason) => write!(f, "the save file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+/// write the whole game state to `SAVE_PATH`
+pub fn save_game(game: &mut Game, world: &World) -> std::io::Result<()> {
+    fs::write(SAVE_PATH, write_game(game, world))
+}
+
+/// read back the state written by `save_game`; the caller rebuilds the FOV map from `map`
+pub fn load_game(game: &mut Game) -> Result<World, LoadError> {
+    read_game(game, &fs::read_to_string(SAVE_PATH)?)
+}
+
+fn write_game(game: &mut Game, world: &World) -> String {
+    // the generator's internal state can't be written out, so restart it from a seed drawn
+    // out of it; the running game and the saved one then roll exactly the same numbers
+    let rng_seed: u64 = game.rng.gen();
+    game.rng = StdRng::seed_from_u64(rng_seed);
+
+    let mut out = Writer::default();
+    out.word(SAVE_MAGIC);
+    out.word(SAVE_VERSION);
+    out.word(game.seed);
+    out.word(rng_seed);
+    out.word(game.dungeon_level);
+    game.map.save(&mut out);
+    game.messages.save(&mut out);
+    world.save(&mut out);
+
+    out.text
+}
+
+fn read_game(game: &mut Game, text: &str) -> Result<World, LoadError> {
+    let mut input = Reader::new(text)?;
+
+    if input.word()? != SAVE_MAGIC {
+        return Err(LoadError::Malformed("not a save file".into()));
+    }
+    let version: u32 = input.parse()?;
+    if version != SAVE_VERSION {
+        return Err(LoadError::Version(version));
+    }
+
+    game.seed = input.parse()?;
+    game.rng = StdRng::seed_from_u64(input.parse()?);
+    game.dungeon_level = input.parse()?;
+    game.map = Save::load(&mut input)?;
+
+    // the map can't be resized to whatever the configuration asks for now
+    let width = game.map.len() as i32;
+    let height = game.map.first().map_or(0, |column| column.len() as i32);
+    if (width, height) != (game.config.map_width, game.config.map_height) {
+        return Err(LoadError::Malformed(format!(
+            "the saved map is {}x{} but the configuration asks for {}x{}",
+            width, height, game.config.map_width, game.config.map_height
+        )));
+    }
+    // and every column must be as tall as the first, or indexing it panics later
+    if let Some(x) = game
+        .map
+        .iter()
+        .position(|column| column.len() as i32 != height)
+    {
+        return Err(LoadError::Malformed(format!(
+            "column {} of the saved map is {} tiles tall instead of {}",
+            x,
+            game.map[x].len(),
+            height
+        )));
+    }
+    game.messages = Save::load(&mut input)?;
+    game.messages.set_limit(game.config.message_limit as usize);
+    let world: World = Save::load(&mut input)?;
+
+    // everything else indexes the map by position without checking
+    for (entity, position) in world.positions.iter() {
+        if position.x < 0 || position.x >= width || position.y < 0 || position.y >= height {
+            return Err(LoadError::Malformed(format!(
+                "entity {}:{} is at {}, {}, off the map",
+                entity.index, entity.generation, position.x, position.y
+            )));
+        }
+    }
+
+    Ok(world)
+}
+
+/// Accumulates the whitespace separated words of a save file.
+#[derive(Default)]
+struct Writer {
+    text: String,
+}
+
+impl Writer {
+    fn word<T: fmt::Display>(&mut self, word: T) {
+        self.text.push_str(&word.to_string());
+        self.text.push('\n');
+    }
+
+    /// strings are quoted so they can contain spaces
+    fn quoted(&mut self, text: &str) {
+        self.text.push('"');
+        for c in text.chars() {
+            match c {
+                '"' => self.text.push_str("\\\""),
+                '\\' => self.text.push_str("\\\\"),
+                '\n' => self.text.push_str("\\n"),
+                c => self.text.push(c),
+            }
+        }
+        self.text.push_str("\"\n");
+    }
+}
+
+/// Hands out the words of a save file one by one.
+struct Reader {
+    words: Vec<String>,
+    position: usize,
+}
+
+impl Reader {
+    fn new(text: &str) -> Result<Self, LoadError> {
+        let mut words = vec![];
+        let mut chars = text.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '"' {
+                chars.next();
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some(c) => word.push(c),
+                            None => return Err(LoadError::Malformed("unfinished string".into())),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(LoadError::Malformed("unfinished string".into())),
+                    }
+                }
+                words.push(word);
+            } else {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+
+        Ok(Reader { words, position: 0 })
+    }
+
+    fn word(&mut self) -> Result<String, LoadError> {
+        let word = self
+            .words
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| LoadError::Malformed("unexpected end of file".into()))?;
+        self.position += 1;
+        Ok(word)
+    }
+
+    fn parse<T: FromStr>(&mut self) -> Result<T, LoadError> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| LoadError::Malformed(format!("unexpected value '{}'", word)))
+    }
+
+    fn unknown<T>(&self, what: &str, word: &str) -> Result<T, LoadError> {
+        Err(LoadError::Malformed(format!("unknown {} '{}'", what, word)))
+    }
+}
+
+/// Something that can be written to and read back from a save file.
+trait Save: Sized {
+    fn save(&self, out: &mut Writer);
+    fn load(input: &mut Reader) -> Result<Self, LoadError>;
+}
+
+impl<T: Save> Save for Vec<T> {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.len());
+        for item in self {
+            item.save(out);
+        }
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let len: usize = input.parse()?;
+        (0..len).map(|_| T::load(input)).collect()
+    }
+}
+
+impl<T: Save> Save for Option<T> {
+    fn save(&self, out: &mut Writer) {
+        match self {
+            Some(value) => {
+                out.word("some");
+                value.save(out);
+            }
+            None => out.word("none"),
+        }
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        match input.word()?.as_str() {
+            "some" => Ok(Some(T::load(input)?)),
+            "none" => Ok(None),
+            other => input.unknown("option", other),
+        }
+    }
+}
+
+impl Save for Color {
+    fn save(&self, out: &mut Writer) {
+        out.word(format!("{},{},{}", self.r, self.g, self.b));
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let word = input.word()?;
+        let channels = word
+            .split(',')
+            .map(|channel| channel.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>();
+
+        match channels.as_deref() {
+            Ok([r, g, b]) => Ok(Color::new(*r, *g, *b)),
+            _ => input.unknown("color", &word),
+        }
+    }
+}
+
+impl Save for Tile {
+    fn save(&self, out: &mut Writer) {
+        // pack the three flags into a single digit, the map is by far the largest part
+        let flags = self.blocked as u8 | (self.block_sight as u8) << 1 | (self.explored as u8) << 2;
+        out.word(flags);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let flags: u8 = input.parse()?;
+        Ok(Tile {
+            blocked: flags & 1 != 0,
+            block_sight: flags & 2 != 0,
+            explored: flags & 4 != 0,
+        })
+    }
+}
+
+impl Save for String {
+    fn save(&self, out: &mut Writer) {
+        out.quoted(self);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        input.word()
+    }
+}
+
+/// marker components carry nothing but their presence
+impl Save for () {
+    fn save(&self, _out: &mut Writer) {}
+
+    fn load(_input: &mut Reader) -> Result<Self, LoadError> {
+        Ok(())
+    }
+}
+
+impl Save for Entity {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.index);
+        out.word(self.generation);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        Ok(Entity {
+            index: input.parse()?,
+            generation: input.parse()?,
+        })
+    }
+}
+
+impl<T: Save> Save for Components<T> {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.iter().count());
+        for (entity, component) in self.iter() {
+            entity.save(out);
+            component.save(out);
+        }
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let len: usize = input.parse()?;
+        let mut components = Components::new();
+        for _ in 0..len {
+            let entity = Entity::load(input)?;
+            components.insert(entity, T::load(input)?);
+        }
+        Ok(components)
+    }
+}
+
+impl Save for World {
+    fn save(&self, out: &mut Writer) {
+        self.entities().save(out);
+        self.player.save(out);
+        self.names.save(out);
+        self.positions.save(out);
+        self.renderables.save(out);
+        self.blocks.save(out);
+        self.fighters.save(out);
+        self.ais.save(out);
+        self.items.save(out);
+        self.equipment.save(out);
+        self.stacks.save(out);
+        self.inventories.save(out);
+        self.actors.save(out);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let mut world = World::new();
+        for entity in Vec::<Entity>::load(input)? {
+            world.restore(entity);
+        }
+        world.player = Entity::load(input)?;
+        world.names = Save::load(input)?;
+        world.positions = Save::load(input)?;
+        world.renderables = Save::load(input)?;
+        world.blocks = Save::load(input)?;
+        world.fighters = Save::load(input)?;
+        world.ais = Save::load(input)?;
+        world.items = Save::load(input)?;
+        world.equipment = Save::load(input)?;
+        world.stacks = Save::load(input)?;
+        world.inventories = Save::load(input)?;
+        world.actors = Save::load(input)?;
+
+        // every handle in the file has to point at one of the saved entities
+        let mut handles = vec![world.player];
+        handles.extend(world.names.entities());
+        handles.extend(world.positions.entities());
+        handles.extend(world.renderables.entities());
+        handles.extend(world.blocks.entities());
+        handles.extend(world.fighters.entities());
+        handles.extend(world.ais.entities());
+        handles.extend(world.items.entities());
+        handles.extend(world.equipment.entities());
+        handles.extend(world.stacks.entities());
+        handles.extend(world.actors.entities());
+        for (owner, inventory) in world.inventories.iter() {
+            handles.push(owner);
+            handles.extend(&inventory.items);
+        }
+        if let Some(entity) = handles.iter().find(|&&entity| !world.contains(entity)) {
+            return Err(LoadError::Malformed(format!(
+                "unknown entity {}:{}",
+                entity.index, entity.generation
+            )));
+        }
+
+        Ok(world)
+    }
+}
+
+impl Save for Position {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.x);
+        out.word(self.y);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        Ok(Position {
+            x: input.parse()?,
+            y: input.parse()?,
+        })
+    }
+}
+
+impl Save for Renderable {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.glyph as u32);
+        self.color.save(out);
+        out.word(self.always_visible);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let glyph = std::char::from_u32(input.parse()?)
+            .ok_or_else(|| LoadError::Malformed("invalid character".into()))?;
+        let mut renderable = Renderable::new(glyph, Color::load(input)?);
+        renderable.always_visible = input.parse()?;
+        Ok(renderable)
+    }
+}
+
+impl Save for Inventory {
+    fn save(&self, out: &mut Writer) {
+        self.items.save(out);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        Ok(Inventory {
+            items: Save::load(input)?,
+        })
+    }
+}
+
+impl Save for Stack {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.quantity);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        Ok(Stack {
+            quantity: input.parse()?,
+        })
+    }
+}
+
+impl Save for Fighter {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.base_max_hp);
+        out.word(self.hp);
+        out.word(self.base_defense);
+        out.word(self.base_power);
+        out.word(self.xp);
+        out.word(self.level);
+        self.on_death.save(out);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let max_hp = input.parse()?;
+        let hp = input.parse()?;
+        let defense = input.parse()?;
+        let power = input.parse()?;
+        let xp = input.parse()?;
+        let level = input.parse()?;
+        let on_death = DeathCallback::load(input)?;
+        let mut fighter = Fighter::new(defense, hp, max_hp, power, xp, on_death);
+        fighter.level = level;
+        Ok(fighter)
+    }
+}
+
+impl Save for DeathCallback {
+    fn save(&self, out: &mut Writer) {
+        out.word(match self {
+            DeathCallback::Player => "player",
+            DeathCallback::Monster => "monster",
+        });
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        match input.word()?.as_str() {
+            "player" => Ok(DeathCallback::Player),
+            "monster" => Ok(DeathCallback::Monster),
+            other => input.unknown("death callback", other),
+        }
+    }
+}
+
+impl Save for Ai {
+    fn save(&self, out: &mut Writer) {
+        match self {
+            Ai::Basic => out.word("basic"),
+            Ai::Confused {
+                previous_ai,
+                num_turns,
+            } => {
+                out.word("confused");
+                out.word(num_turns);
+                previous_ai.save(out);
+            }
+            Ai::Fleeing { threshold } => {
+                out.word("fleeing");
+                out.word(threshold);
+            }
+            Ai::Ranged { range } => {
+                out.word("ranged");
+                out.word(range);
+            }
+            Ai::Wanderer => out.word("wanderer"),
+        }
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        match input.word()?.as_str() {
+            "basic" => Ok(Ai::Basic),
+            "confused" => {
+                let num_turns = input.parse()?;
+                let previous_ai = Box::new(Ai::load(input)?);
+                Ok(Ai::Confused {
+                    previous_ai,
+                    num_turns,
+                })
+            }
+            "fleeing" => Ok(Ai::Fleeing {
+                threshold: input.parse()?,
+            }),
+            "ranged" => Ok(Ai::Ranged {
+                range: input.parse()?,
+            }),
+            "wanderer" => Ok(Ai::Wanderer),
+            other => input.unknown("ai", other),
+        }
+    }
+}
+
+impl Save for Actor {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.speed);
+        out.word(self.energy);
+        // the turns the boost has left, 0 when there's none
+        match self.boost {
+            Some((boost, turns)) => {
+                out.word(turns);
+                boost.save(out);
+            }
+            None => out.word(0),
+        }
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let mut actor = Actor::new(input.parse()?);
+        actor.energy = input.parse()?;
+        let turns = input.parse()?;
+        if turns > 0 {
+            actor.boost = Some((Boost::load(input)?, turns));
+        }
+        if actor.speed < 1 {
+            return Err(LoadError::Malformed(format!("speed {}", actor.speed)));
+        }
+        Ok(actor)
+    }
+}
+
+impl Save for Boost {
+    fn save(&self, out: &mut Writer) {
+        out.word(match self {
+            Boost::Haste => "haste",
+            Boost::Slow => "slow",
+        });
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        match input.word()?.as_str() {
+            "haste" => Ok(Boost::Haste),
+            "slow" => Ok(Boost::Slow),
+            other => input.unknown("boost", other),
+        }
+    }
+}
+
+impl Save for Item {
+    fn save(&self, out: &mut Writer) {
+        out.word(match self {
+            Item::Heal => "heal",
+            Item::Equip => "equip",
+            Item::Lightning => "lightning",
+            Item::Fireball => "fireball",
+            Item::Confuse => "confuse",
+            Item::Haste => "haste",
+            Item::Slow => "slow",
+        });
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        match input.word()?.as_str() {
+            "heal" => Ok(Item::Heal),
+            "equip" => Ok(Item::Equip),
+            "lightning" => Ok(Item::Lightning),
+            "fireball" => Ok(Item::Fireball),
+            "confuse" => Ok(Item::Confuse),
+            "haste" => Ok(Item::Haste),
+            "slow" => Ok(Item::Slow),
+            other => input.unknown("item", other),
+        }
+    }
+}
+
+impl Save for Equipment {
+    fn save(&self, out: &mut Writer) {
+        self.slot.save(out);
+        out.word(self.equipped);
+        out.word(self.power_bonus);
+        out.word(self.defense_bonus);
+        out.word(self.max_hp_bonus);
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let slot = Slot::load(input)?;
+        let equipped = input.parse()?;
+        let mut equipment = Equipment::new(slot, input.parse()?, input.parse()?, input.parse()?);
+        equipment.equipped = equipped;
+        Ok(equipment)
+    }
+}
+
+impl Save for Slot {
+    fn save(&self, out: &mut Writer) {
+        out.word(match self {
+            Slot::MainHand => "main-hand",
+            Slot::OffHand => "off-hand",
+            Slot::Head => "head",
+            Slot::Body => "body",
+            Slot::Ring => "ring",
+        });
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        match input.word()?.as_str() {
+            "main-hand" => Ok(Slot::MainHand),
+            "off-hand" => Ok(Slot::OffHand),
+            "head" => Ok(Slot::Head),
+            "body" => Ok(Slot::Body),
+            "ring" => Ok(Slot::Ring),
+            other => input.unknown("slot", other),
+        }
+    }
+}
+
+impl Save for Messages {
+    fn save(&self, out: &mut Writer) {
+        out.word(self.len());
+        for message in self.iter() {
+            out.quoted(&message.text);
+            message.color.save(out);
+            out.word(message.count);
+        }
+    }
+
+    fn load(input: &mut Reader) -> Result<Self, LoadError> {
+        let len: usize = input.parse()?;
+        // keep them all, the game cuts them down to its own limit
+        let mut messages = Messages::new(len);
+        for _ in 0..len {
+            messages.push(Message {
+                text: input.word()?,
+                color: Color::load(input)?,
+                count: input.parse()?,
+            });
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, CONFIG_PATH};
+    use crate::templates::Templates;
+    use crate::{empty_game, new_game};
+    use tcod::colors::LIGHT_BLUE;
+
+    fn setup() -> (Config, Templates) {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        let templates = Templates::load(&config.templates_path).unwrap();
+        (config, templates)
+    }
+
+    /// every component of the entity, in a form that can be compared
+    fn describe(entity: Entity, world: &World) -> String {
+        format!(
+            "{:?}",
+            (
+                world.names.get(entity),
+                world.positions.get(entity),
+                world.renderables.get(entity),
+                world.blocks.contains(entity),
+                world.fighters.get(entity),
+                world.ais.get(entity),
+                world.items.get(entity),
+                world.equipment.get(entity),
+                world.stacks.get(entity),
+                world.inventories.get(entity),
+                world.actors.get(entity),
+            )
+        )
+    }
+
+    #[test]
+    fn a_saved_game_loads_back_the_same() {
+        let (config, templates) = setup();
+        let (mut game, world) = new_game(7, &config, &templates);
+        for x in 0..10 {
+            game.map[x][5].explored = true;
+        }
+        game.messages.add("A \"quoted\" message", LIGHT_BLUE);
+        game.messages.add("A \"quoted\" message", LIGHT_BLUE);
+        game.dungeon_level = 3;
+
+        let text = write_game(&mut game, &world);
+        let mut loaded = empty_game(0, &config, &templates);
+        let loaded_world = read_game(&mut loaded, &text).unwrap();
+
+        assert_eq!(loaded.seed, 7);
+        assert_eq!(loaded.dungeon_level, 3);
+        assert_eq!(loaded.rng.gen::<u64>(), game.rng.gen::<u64>());
+        assert_eq!(format!("{:?}", loaded.map), format!("{:?}", game.map));
+        assert!(loaded.map[9][5].explored && !loaded.map[10][5].explored);
+
+        let messages = |game: &Game| -> Vec<_> {
+            game.messages
+                .iter()
+                .map(|message| (message.display(), message.color))
+                .collect()
+        };
+        assert_eq!(messages(&loaded), messages(&game));
+
+        assert_eq!(loaded_world.player, world.player);
+        assert_eq!(loaded_world.entities(), world.entities());
+        for entity in world.entities() {
+            assert_eq!(describe(entity, &loaded_world), describe(entity, &world));
+        }
+    }
+
+    #[test]
+    fn an_entity_off_the_map_is_malformed() {
+        let (config, templates) = setup();
+        let (mut game, mut world) = new_game(7, &config, &templates);
+        world.set_pos(world.player, config.map_width, 0);
+
+        let text = write_game(&mut game, &world);
+        let mut loaded = empty_game(0, &config, &templates);
+        match read_game(&mut loaded, &text) {
+            Err(LoadError::Malformed(reason)) => assert!(reason.contains("off the map")),
+            other => panic!("expected a malformed save, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn a_short_column_is_malformed() {
+        let (config, templates) = setup();
+        let (mut game, world) = new_game(7, &config, &templates);
+        game.map[10].pop();
+
+        let text = write_game(&mut game, &world);
+        let mut loaded = empty_game(0, &config, &templates);
+        match read_game(&mut loaded, &text) {
+            Err(LoadError::Malformed(reason)) => assert!(reason.contains("column 10")),
+            other => panic!("expected a malformed save, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let (config, templates) = setup();
+        let (mut game, world) = new_game(7, &config, &templates);
+        let text =
+            write_game(&mut game, &world).replacen(&format!("\n{}\n", SAVE_VERSION), "\n1\n", 1);
+        let mut loaded = empty_game(0, &config, &templates);
+        assert!(matches!(
+            read_game(&mut loaded, &text),
+            Err(LoadError::Version(1))
+        ));
+    }
+}
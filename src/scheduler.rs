@@ -0,0 +1,193 @@
+//! Who acts next. Every actor has an energy pool that refills at its speed as game time
+//! passes, and every action drains it by the action's cost. Whoever has the most energy acts
+//! once they're no longer in debt, so an entity twice as fast as the player gets two moves
+//! for each of theirs, and one half as fast gets a move every other turn.
+
+use crate::world::{Entity, World};
+use std::cmp;
+
+/// the speed of the player and most monsters; also the energy an ordinary action costs, so at
+/// this speed every action takes exactly one tick of game time
+pub const NORMAL_SPEED: i32 = 100;
+
+// what the actions cost
+pub const MOVE_COST: i32 = 100;
+pub const ATTACK_COST: i32 = 100;
+/// quaffing or reading is quicker than a step, but not free
+pub const USE_ITEM_COST: i32 = 50;
+/// doing nothing still takes as long as a step, so waiting lets everyone else move
+pub const WAIT_COST: i32 = 100;
+
+/// A temporary change to the speed, from a potion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Boost {
+    /// twice as fast
+    Haste,
+    /// half as fast
+    Slow,
+}
+
+/// Something that takes turns: the player and the monsters
+#[derive(Clone, Copy, Debug)]
+pub struct Actor {
+    /// energy regained per tick, before any boost
+    pub speed: i32,
+    /// ready to act at 0 or more, below that the actor is waiting for its turn
+    pub energy: i32,
+    /// the boost in effect and how many of the actor's own turns it still lasts
+    pub boost: Option<(Boost, i32)>,
+}
+
+impl Actor {
+    pub fn new(speed: i32) -> Self {
+        Actor {
+            speed,
+            energy: 0,
+            boost: None,
+        }
+    }
+
+    /// the speed with the boost applied
+    pub fn speed(&self) -> i32 {
+        match self.boost {
+            Some((Boost::Haste, _)) => self.speed * 2,
+            Some((Boost::Slow, _)) => cmp::max(self.speed / 2, 1),
+            None => self.speed,
+        }
+    }
+}
+
+/// the actor whose turn it is, advancing game time until someone is ready if needed; on a tie
+/// the one spawned first goes first, which is always the player
+pub fn next_actor(world: &mut World) -> Option<Entity> {
+    loop {
+        let ready = world
+            .actors
+            .iter()
+            .filter(|(_, actor)| actor.energy >= 0)
+            .min_by_key(|(_, actor)| -actor.energy)
+            .map(|(entity, _)| entity);
+        if ready.is_some() {
+            return ready;
+        }
+
+        // skip straight to the tick where the first of them is out of debt
+        let ticks = world
+            .actors
+            .iter()
+            .map(|(_, actor)| (-actor.energy + actor.speed() - 1) / actor.speed())
+            .min()?;
+        for entity in world.actors.entities() {
+            if let Some(actor) = world.actors.get_mut(entity) {
+                actor.energy += ticks * actor.speed();
+            }
+        }
+    }
+}
+
+/// pay for an action
+pub fn spend(entity: Entity, cost: i32, world: &mut World) {
+    if let Some(actor) = world.actors.get_mut(entity) {
+        actor.energy -= cost;
+    }
+}
+
+/// the entity's turn has come: count down its boost, returning the boost if it just wore off
+pub fn start_turn(entity: Entity, world: &mut World) -> Option<Boost> {
+    let actor = world.actors.get_mut(entity)?;
+    let (boost, turns) = actor.boost?;
+    if turns > 1 {
+        actor.boost = Some((boost, turns - 1));
+        None
+    } else {
+        actor.boost = None;
+        Some(boost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a world with an actor of each speed, spawned in order, the first being the player
+    fn actors(speeds: &[i32]) -> (World, Vec<Entity>) {
+        let mut world = World::new();
+        let entities: Vec<_> = speeds
+            .iter()
+            .map(|&speed| {
+                let entity = world.spawn();
+                world.actors.insert(entity, Actor::new(speed));
+                entity
+            })
+            .collect();
+        world.player = entities[0];
+        (world, entities)
+    }
+
+    /// who acts in the next `turns` turns, everyone doing ordinary actions
+    fn turns(world: &mut World, turns: usize) -> Vec<Entity> {
+        (0..turns)
+            .map(|_| {
+                let entity = next_actor(world).unwrap();
+                start_turn(entity, world);
+                spend(entity, MOVE_COST, world);
+                entity
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fast_actors_move_twice_and_slow_ones_every_other_turn() {
+        let (mut world, entities) = actors(&[NORMAL_SPEED, 200, 50]);
+        let (player, fast, slow) = (entities[0], entities[1], entities[2]);
+
+        let order = turns(&mut world, 70);
+        let count = |entity| order.iter().filter(|&&e| e == entity).count();
+        assert_eq!((count(player), count(fast), count(slow)), (20, 40, 10));
+
+        // between two turns of the player, always two of the fast one
+        for between in order
+            .split(|&e| e == player)
+            .skip(1)
+            .filter(|t| !t.is_empty())
+        {
+            assert_eq!(between.iter().filter(|&&e| e == fast).count(), 2);
+        }
+    }
+
+    #[test]
+    fn the_player_goes_first_on_a_tie() {
+        let (mut world, entities) = actors(&[NORMAL_SPEED, NORMAL_SPEED]);
+        assert_eq!(
+            turns(&mut world, 4),
+            vec![entities[0], entities[1], entities[0], entities[1]]
+        );
+    }
+
+    #[test]
+    fn cheap_actions_come_back_sooner() {
+        let (mut world, entities) = actors(&[NORMAL_SPEED, NORMAL_SPEED]);
+        let (player, monster) = (entities[0], entities[1]);
+        assert_eq!(next_actor(&mut world), Some(player));
+        spend(player, USE_ITEM_COST, &mut world);
+        assert_eq!(next_actor(&mut world), Some(monster));
+        spend(monster, MOVE_COST, &mut world);
+        // half the debt, so the player gets in again before the monster
+        assert_eq!(next_actor(&mut world), Some(player));
+    }
+
+    #[test]
+    fn boosts_change_the_speed_and_wear_off() {
+        let (mut world, entities) = actors(&[NORMAL_SPEED, NORMAL_SPEED]);
+        let (player, monster) = (entities[0], entities[1]);
+        world.actors.get_mut(player).unwrap().boost = Some((Boost::Haste, 2));
+        world.actors.get_mut(monster).unwrap().boost = Some((Boost::Slow, 5));
+        assert_eq!(world.actors.get(player).unwrap().speed(), 200);
+        assert_eq!(world.actors.get(monster).unwrap().speed(), 50);
+
+        assert_eq!(start_turn(player, &mut world), None);
+        assert_eq!(start_turn(player, &mut world), Some(Boost::Haste));
+        assert_eq!(world.actors.get(player).unwrap().speed(), NORMAL_SPEED);
+        assert_eq!(start_turn(player, &mut world), None);
+    }
+}
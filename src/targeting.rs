@@ -0,0 +1,275 @@
+use crate::domain::{can_step, Game, Map};
+use crate::keymap::Keymap;
+use crate::render::Renderer;
+use crate::render_all;
+use crate::world::{Entity, World};
+use tcod::colors::*;
+use tcod::input::{Event, KeyCode};
+use tcod::line::Line;
+
+/// What the player picked in targeting mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Tile(i32, i32),
+    Monster(Entity),
+}
+
+/// Whether any tile will do, or the cursor has to be on a monster
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetKind {
+    Tile,
+    Monster,
+    /// any tile, showing the way something thrown there would fly
+    Throw,
+}
+
+/// Let the player move a cursor over the map with the movement keys or the mouse, and confirm
+/// it with Enter or a left-click. Only tiles in FOV and, if given, within `max_range` of the
+/// player can be chosen. Tab jumps between visible monsters. Returns None when the player
+/// cancels with Escape or a right-click.
+pub fn choose_target(
+    renderer: &mut impl Renderer,
+    keymap: &Keymap,
+    game: &mut Game,
+    world: &World,
+    kind: TargetKind,
+    max_range: Option<f32>,
+) -> Option<Target> {
+    // start on the closest monster worth aiming at, or on the player
+    let monsters = visible_monsters(game, world, max_range);
+    let start = monsters.first().copied().unwrap_or(world.player);
+    let mut cursor = world.pos(start).unwrap_or_default();
+
+    loop {
+        let target = target_at(cursor, game, world, kind, max_range);
+
+        // draw the game with the names under the cursor, and the cursor itself on top
+        render_all(renderer, cursor, game, world, false);
+        if kind == TargetKind::Throw {
+            if let Some(start) = world.pos(world.player) {
+                for (x, y) in flight_path(start, cursor, &game.map, world) {
+                    renderer.blend_background(x, y, LIGHT_YELLOW, 0.4);
+                }
+            }
+        }
+        let (x, y) = cursor;
+        let color = if target.is_some() { LIGHT_GREEN } else { RED };
+        renderer.blend_background(x, y, color, 0.8);
+        renderer.flush();
+
+        // closing the window cancels, like anything else that leaves the game
+        if renderer.window_closed() {
+            return None;
+        }
+        let mut confirmed = false;
+        match renderer.wait_for_event() {
+            Event::Mouse(mouse) => {
+                let (x, y) = (mouse.cx as i32, mouse.cy as i32);
+
+                // the cursor follows the mouse while it's over the map
+                if x < game.config.map_width && y < game.config.map_height {
+                    cursor = (x, y);
+                }
+                if mouse.rbutton_pressed {
+                    return None;
+                }
+                confirmed = mouse.lbutton_pressed;
+            }
+            Event::Key(key) => {
+                let (x, y) = cursor;
+                let direction = keymap.action(key).and_then(|action| action.direction());
+                match key.code {
+                    KeyCode::Escape => return None,
+                    KeyCode::Enter | KeyCode::NumPadEnter => confirmed = true,
+                    KeyCode::Tab if !monsters.is_empty() => {
+                        // cycle to the monster after the one under the cursor
+                        let current = monsters
+                            .iter()
+                            .position(|&monster| world.pos(monster) == Some(cursor));
+                        let next = current.map_or(0, |index| (index + 1) % monsters.len());
+                        cursor = world.pos(monsters[next]).unwrap_or(cursor);
+                    }
+                    _ => {
+                        if let Some((dx, dy)) = direction {
+                            cursor = (x + dx, y + dy);
+                        }
+                    }
+                }
+
+                // keep the cursor on the map
+                cursor = (
+                    cursor.0.clamp(0, game.config.map_width - 1),
+                    cursor.1.clamp(0, game.config.map_height - 1),
+                );
+            }
+        }
+
+        if confirmed {
+            if let Some(target) = target_at(cursor, game, world, kind, max_range) {
+                return Some(target);
+            }
+        }
+    }
+}
+
+/// what the player would get by confirming the cursor at `(x, y)`, if it's a valid choice
+fn target_at(
+    (x, y): (i32, i32),
+    game: &Game,
+    world: &World,
+    kind: TargetKind,
+    max_range: Option<f32>,
+) -> Option<Target> {
+    let player = world.positions.get(world.player)?;
+    let in_fov = game.fov.is_in_fov(x, y);
+    let in_range = max_range.is_none_or(|range| player.distance(x, y) <= range);
+    if !in_fov || !in_range {
+        return None;
+    }
+
+    match kind {
+        TargetKind::Tile | TargetKind::Throw => Some(Target::Tile(x, y)),
+        TargetKind::Monster => world
+            .fighters
+            .iter()
+            .map(|(entity, _)| entity)
+            .find(|&entity| entity != world.player && world.pos(entity) == Some((x, y)))
+            .map(Target::Monster),
+    }
+}
+
+/// the tiles something thrown from `from` toward `to` passes over, ending where it lands: on
+/// `to`, in front of the wall in the way, or on the first one blocking the way. It can't
+/// slip between the corners of walls any more than a step can.
+pub fn flight_path(from: (i32, i32), to: (i32, i32), map: &Map, world: &World) -> Vec<(i32, i32)> {
+    let mut path = vec![];
+    let mut previous = from;
+    for (x, y) in Line::new(from, to) {
+        if !can_step(previous.0, previous.1, x - previous.0, y - previous.1, map) {
+            break;
+        }
+        path.push((x, y));
+        if world
+            .blocks
+            .iter()
+            .any(|(entity, _)| world.pos(entity) == Some((x, y)))
+        {
+            break;
+        }
+        previous = (x, y);
+    }
+    path
+}
+
+/// the monsters in FOV and range, closest to the player first
+fn visible_monsters(game: &Game, world: &World, max_range: Option<f32>) -> Vec<Entity> {
+    let player = match world.positions.get(world.player) {
+        Some(player) => *player,
+        None => return vec![],
+    };
+    let mut monsters: Vec<_> = world
+        .fighters
+        .iter()
+        .filter_map(|(entity, _)| Some((entity, *world.positions.get(entity)?)))
+        .filter(|&(entity, position)| {
+            entity != world.player
+                && game.fov.is_in_fov(position.x, position.y)
+                && max_range.is_none_or(|range| player.distance_to(&position) <= range)
+        })
+        .collect();
+
+    monsters.sort_by(|(_, a), (_, b)| player.distance_to(a).total_cmp(&player.distance_to(b)));
+    monsters.into_iter().map(|(entity, _)| entity).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, CONFIG_PATH};
+    use crate::new_game;
+    use crate::render::{scripted, GridRenderer};
+    use crate::templates::Templates;
+
+    /// a new game without any monsters, and a screen to aim on
+    fn setup(events: Vec<Event>) -> (Game, World, GridRenderer) {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        let templates = Templates::load(&config.templates_path).unwrap();
+        let (mut game, mut world) = new_game(3, &config, &templates);
+        for monster in world.fighters.entities() {
+            if monster != world.player {
+                world.despawn(monster);
+            }
+        }
+        // the first frame works out what's in sight
+        let mut grid = GridRenderer::new(config.screen_width, config.screen_height);
+        render_all(&mut grid, (-1, -1), &mut game, &world, true);
+        grid.events.extend(events);
+        (game, world, grid)
+    }
+
+    #[test]
+    fn the_cursor_moves_with_the_keymap_and_enter_confirms() {
+        let events = vec![
+            scripted::key(KeyCode::Right),
+            scripted::key(KeyCode::Down),
+            scripted::key(KeyCode::Enter),
+        ];
+        let (mut game, world, mut grid) = setup(events);
+        let (x, y) = world.pos(world.player).unwrap();
+        let keymap = Keymap::default();
+
+        let target = choose_target(
+            &mut grid,
+            &keymap,
+            &mut game,
+            &world,
+            TargetKind::Tile,
+            None,
+        );
+        assert_eq!(target, Some(Target::Tile(x + 1, y + 1)));
+    }
+
+    #[test]
+    fn escape_and_a_click_out_of_range_get_nothing() {
+        let (mut game, world, mut grid) = setup(vec![scripted::key(KeyCode::Escape)]);
+        let keymap = Keymap::default();
+        let target = choose_target(
+            &mut grid,
+            &keymap,
+            &mut game,
+            &world,
+            TargetKind::Tile,
+            None,
+        );
+        assert_eq!(target, None);
+
+        // the click is too far, the script then runs out, which cancels
+        let (x, y) = world.pos(world.player).unwrap();
+        grid.events.push_back(scripted::mouse(x + 3, y, true));
+        let target = choose_target(
+            &mut grid,
+            &keymap,
+            &mut game,
+            &world,
+            TargetKind::Tile,
+            Some(2.0),
+        );
+        assert_eq!(target, None);
+    }
+
+    #[test]
+    fn no_monster_no_monster_target() {
+        let events = vec![scripted::key(KeyCode::Enter)];
+        let (mut game, world, mut grid) = setup(events);
+        let keymap = Keymap::default();
+        let target = choose_target(
+            &mut grid,
+            &keymap,
+            &mut game,
+            &world,
+            TargetKind::Monster,
+            None,
+        );
+        assert_eq!(target, None);
+    }
+}
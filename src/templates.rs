@@ -0,0 +1,656 @@
+use crate::domain::{
+    self, from_dungeon_level, spawn_at, Ai, DeathCallback, Equipment, Fighter, Inventory, Item,
+    Slot, Stack, Transition,
+};
+use crate::scheduler::{Actor, NORMAL_SPEED};
+use crate::toml::{DataError, Document, Entry, Table, TableReader, Value};
+use crate::world::{Entity, World};
+use rand::Rng;
+use tcod::colors::Color;
+
+/// The definition of a kind of monster, read from the templates file
+#[derive(Clone, Debug)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub fighter: Fighter,
+    pub ai: Ai,
+    /// how quickly it acts, `NORMAL_SPEED` being as fast as the player
+    pub speed: i32,
+    /// what it may start out with; `None` for monsters that never carry anything
+    pub carries: Option<Vec<Carried>>,
+    /// shown when the player looks at one, may be empty
+    pub description: String,
+    /// spawn weight from each dungeon level onwards
+    pub spawn: Vec<Transition>,
+}
+
+/// An item a monster spawns with some of the time
+#[derive(Clone, Copy, Debug)]
+pub struct Carried {
+    /// index into `Templates::items`
+    pub item: usize,
+    /// percent chance of having it
+    pub chance: i32,
+}
+
+/// The definition of a kind of item, read from the templates file
+#[derive(Clone, Debug)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub item: Item,
+    pub equipment: Option<Equipment>,
+    /// whether the ones the player carries pile up into a single inventory entry
+    pub stackable: bool,
+    /// what it does, shown when the player looks at one; may be empty
+    pub description: String,
+    /// spawn weight from each dungeon level onwards
+    pub spawn: Vec<Transition>,
+}
+
+/// Every monster and item the dungeon can be populated with
+#[derive(Clone, Debug)]
+pub struct Templates {
+    pub monsters: Vec<MonsterTemplate>,
+    pub items: Vec<ItemTemplate>,
+}
+
+impl MonsterTemplate {
+    /// create a monster of this kind at the given position, with whatever it happens to carry
+    /// out of `items`
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        x: i32,
+        y: i32,
+        items: &[ItemTemplate],
+        rng: &mut impl Rng,
+    ) -> Entity {
+        let monster = spawn_at(world, x, y, self.glyph, &self.name, self.color, true);
+        world.fighters.insert(monster, self.fighter);
+        world.ais.insert(monster, self.ai.clone());
+        world.actors.insert(monster, Actor::new(self.speed));
+
+        if let Some(carries) = &self.carries {
+            world.inventories.insert(monster, Inventory::default());
+            for carried in carries {
+                if rng.gen_range(0, 100) < carried.chance {
+                    let item = items[carried.item].spawn(world, x, y);
+                    world.positions.remove(item);
+                    domain::put_in_inventory(monster, item, world);
+                    domain::wear_if_free(monster, item, world);
+                }
+            }
+        }
+        monster
+    }
+}
+
+impl ItemTemplate {
+    /// create an item of this kind at the given position
+    pub fn spawn(&self, world: &mut World, x: i32, y: i32) -> Entity {
+        let item = spawn_at(world, x, y, self.glyph, &self.name, self.color, false);
+        world.items.insert(item, self.item);
+        if let Some(equipment) = self.equipment {
+            world.equipment.insert(item, equipment);
+        }
+        if self.stackable {
+            world.stacks.insert(item, Stack { quantity: 1 });
+        }
+        item
+    }
+}
+
+impl Templates {
+    /// read and validate the templates file, the error points at the first bad entry
+    pub fn load(path: &str) -> Result<Self, DataError> {
+        Self::read(&Document::load(path)?)
+    }
+
+    fn read(document: &Document) -> Result<Self, DataError> {
+        if let Some(entry) = document.root().entries.first() {
+            return Err(document.error(
+                entry.line,
+                format!("`{}` must be inside a [[monster]] or [[item]]", entry.key),
+            ));
+        }
+        if let Some(table) = document.tables.iter().find(|table| {
+            !(table.array && (table.name == "monster" || table.name == "item")) && table.line > 0
+        }) {
+            return Err(document.error(
+                table.line,
+                format!(
+                    "unknown section {}, expected [[monster]] or [[item]]",
+                    table.header()
+                ),
+            ));
+        }
+
+        // items first, monsters refer to them by name
+        let mut items = vec![];
+        for (index, table) in document.tables_named("item").enumerate() {
+            let mut reader = TableReader::new(document, table, describe("item", index, table));
+            items.push(read_item(&mut reader)?);
+            reader.finish()?;
+        }
+
+        let mut monsters = vec![];
+        for (index, table) in document.tables_named("monster").enumerate() {
+            let mut reader = TableReader::new(document, table, describe("monster", index, table));
+            monsters.push(read_monster(&mut reader, &items)?);
+            reader.finish()?;
+        }
+
+        // names identify templates, eg. in the save file and messages
+        let names: Vec<_> = monsters
+            .iter()
+            .map(|m| &m.name)
+            .chain(items.iter().map(|i| &i.name))
+            .collect();
+        for (index, name) in names.iter().enumerate() {
+            if names[..index].contains(name) {
+                return Err(document.error(0, format!("'{}' is defined more than once", name)));
+            }
+        }
+
+        // the spawn tables must offer something to pick on every level
+        check_spawn_weights(document, "monster", monsters.iter().map(|m| &m.spawn))?;
+        check_spawn_weights(document, "item", items.iter().map(|i| &i.spawn))?;
+
+        Ok(Templates { monsters, items })
+    }
+
+    /// the description of the monster or item called `name`, if there's one
+    pub fn description(&self, name: &str) -> Option<&str> {
+        let monsters = self.monsters.iter().map(|m| (&m.name, &m.description));
+        let items = self.items.iter().map(|i| (&i.name, &i.description));
+        monsters
+            .chain(items)
+            .find(|(template_name, description)| *template_name == name && !description.is_empty())
+            .map(|(_, description)| description.as_str())
+    }
+}
+
+/// how a template is called in error messages, by name when it has one
+fn describe(kind: &str, index: usize, table: &Table) -> String {
+    match table.get("name").map(|entry| &entry.value) {
+        Some(Value::String(name)) => format!("{} '{}'", kind, name),
+        _ => format!("{} #{}", kind, index + 1),
+    }
+}
+
+fn read_monster(
+    reader: &mut TableReader,
+    items: &[ItemTemplate],
+) -> Result<MonsterTemplate, DataError> {
+    let name = read_name(reader)?;
+    let glyph = read_glyph(reader)?;
+    let color = read_color(reader)?;
+
+    let hp = read_stat(reader, "hp", 1)?;
+    let defense = read_stat(reader, "defense", 0)?;
+    let power = read_stat(reader, "power", 0)?;
+    let xp = read_stat(reader, "xp", 0)?;
+    let fighter = Fighter::new(defense, hp, hp, power, xp, DeathCallback::Monster);
+
+    let ai = read_ai(reader)?;
+    let speed = reader.integer_or("speed", i64::from(NORMAL_SPEED))?;
+    if speed < 1 || speed > i64::from(i32::MAX) {
+        let line = reader.required("speed")?.line;
+        return Err(reader.error(line, "`speed` must be at least 1"));
+    }
+
+    let carries = read_carries(reader, items)?;
+    let description = reader.string_or("description", "")?;
+    let spawn = read_spawn(reader)?;
+
+    Ok(MonsterTemplate {
+        name,
+        glyph,
+        color,
+        fighter,
+        ai,
+        speed: speed as i32,
+        carries,
+        description,
+        spawn,
+    })
+}
+
+fn read_ai(reader: &mut TableReader) -> Result<Ai, DataError> {
+    let entry = reader.required("ai")?;
+    let name = match &entry.value {
+        Value::String(name) => name.as_str(),
+        _ => return Err(reader.expected(entry, "a string")),
+    };
+
+    // the settings of one ai make no sense for the others
+    for (key, owner) in [("flee_below", "fleeing"), ("range", "ranged")] {
+        if name != owner {
+            if let Some(setting) = reader.optional(key) {
+                return Err(reader.error(
+                    setting.line,
+                    format!("`{}` only applies to ai = \"{}\"", key, owner),
+                ));
+            }
+        }
+    }
+
+    match name {
+        "basic" => Ok(Ai::Basic),
+        "fleeing" => Ok(Ai::Fleeing {
+            threshold: read_percent(reader, "flee_below")?,
+        }),
+        "ranged" => Ok(Ai::Ranged {
+            range: read_stat(reader, "range", 2)?,
+        }),
+        "wanderer" => Ok(Ai::Wanderer),
+        _ => Err(reader.error(
+            entry.line,
+            format!(
+                "unknown ai '{}', expected one of 'basic', 'fleeing', 'ranged' or 'wanderer'",
+                name
+            ),
+        )),
+    }
+}
+
+fn read_item(reader: &mut TableReader) -> Result<ItemTemplate, DataError> {
+    let name = read_name(reader)?;
+    let glyph = read_glyph(reader)?;
+    let color = read_color(reader)?;
+
+    let entry = reader.required("effect")?;
+    let item = match &entry.value {
+        Value::String(effect) => match effect.as_str() {
+            "heal" => Item::Heal,
+            "equip" => Item::Equip,
+            "lightning" => Item::Lightning,
+            "fireball" => Item::Fireball,
+            "confuse" => Item::Confuse,
+            "haste" => Item::Haste,
+            "slow" => Item::Slow,
+            _ => {
+                return Err(reader.error(
+                    entry.line,
+                    format!(
+                        "unknown effect '{}', expected one of 'heal', 'equip', 'lightning', \
+                         'fireball', 'confuse', 'haste' or 'slow'",
+                        effect
+                    ),
+                ))
+            }
+        },
+        _ => return Err(reader.expected(entry, "a string")),
+    };
+
+    // equipment describes where it's worn and what it gives, nothing else can have those
+    let equipment = match item {
+        Item::Equip => Some(read_equipment(reader)?),
+        _ => {
+            let keys = ["slot", "power_bonus", "defense_bonus", "max_hp_bonus"];
+            if let Some(entry) = keys.iter().find_map(|key| reader.optional(key)) {
+                return Err(reader.error(
+                    entry.line,
+                    format!("`{}` only applies to effect = \"equip\"", entry.key),
+                ));
+            }
+            None
+        }
+    };
+
+    // equipment is worn one piece at a time, so it can't stack
+    let stackable = reader.boolean_or("stackable", equipment.is_none())?;
+    if stackable && equipment.is_some() {
+        let line = reader.required("stackable")?.line;
+        return Err(reader.error(line, "`stackable` doesn't apply to effect = \"equip\""));
+    }
+
+    let description = reader.string_or("description", "")?;
+    let spawn = read_spawn(reader)?;
+
+    Ok(ItemTemplate {
+        name,
+        glyph,
+        color,
+        item,
+        equipment,
+        stackable,
+        description,
+        spawn,
+    })
+}
+
+fn read_equipment(reader: &mut TableReader) -> Result<Equipment, DataError> {
+    let entry = reader.required("slot")?;
+    let slot = match &entry.value {
+        Value::String(slot) => match slot.as_str() {
+            "main hand" => Slot::MainHand,
+            "off hand" => Slot::OffHand,
+            "head" => Slot::Head,
+            "body" => Slot::Body,
+            "ring" => Slot::Ring,
+            _ => {
+                return Err(reader.error(
+                    entry.line,
+                    format!(
+                        "unknown slot '{}', expected one of 'main hand', 'off hand', 'head', \
+                         'body' or 'ring'",
+                        slot
+                    ),
+                ))
+            }
+        },
+        _ => return Err(reader.expected(entry, "a string")),
+    };
+
+    let power_bonus = read_bonus(reader, "power_bonus")?;
+    let defense_bonus = read_bonus(reader, "defense_bonus")?;
+    let max_hp_bonus = read_bonus(reader, "max_hp_bonus")?;
+
+    Ok(Equipment::new(
+        slot,
+        power_bonus,
+        defense_bonus,
+        max_hp_bonus,
+    ))
+}
+
+fn read_name(reader: &mut TableReader) -> Result<String, DataError> {
+    let name = reader.string("name")?;
+    if name.trim().is_empty() {
+        let line = reader.required("name")?.line;
+        return Err(reader.error(line, "`name` can't be empty"));
+    }
+    Ok(name)
+}
+
+fn read_glyph(reader: &mut TableReader) -> Result<char, DataError> {
+    let entry = reader.required("glyph")?;
+    match &entry.value {
+        Value::String(glyph) if glyph.chars().count() == 1 => Ok(glyph.chars().next().unwrap()),
+        Value::String(_) => Err(reader.error(entry.line, "`glyph` must be a single character")),
+        _ => Err(reader.expected(entry, "a string")),
+    }
+}
+
+fn read_color(reader: &mut TableReader) -> Result<Color, DataError> {
+    let entry = reader.required("color")?;
+    color_from(entry).ok_or_else(|| reader.expected(entry, "an array of three numbers 0-255"))
+}
+
+/// a `[r, g, b]` array
+pub fn color_from(entry: &Entry) -> Option<Color> {
+    match &entry.value {
+        Value::Array(channels) if channels.len() == 3 => {
+            let mut rgb = [0; 3];
+            for (channel, value) in rgb.iter_mut().zip(channels) {
+                match value {
+                    Value::Integer(value) if (0..=255).contains(value) => *channel = *value as u8,
+                    _ => return None,
+                }
+            }
+            Some(Color::new(rgb[0], rgb[1], rgb[2]))
+        }
+        _ => None,
+    }
+}
+
+fn read_stat(reader: &mut TableReader, key: &'static str, minimum: i64) -> Result<i32, DataError> {
+    let value = reader.integer(key)?;
+    if value < minimum || value > i64::from(i32::MAX) {
+        let line = reader.required(key)?.line;
+        return Err(reader.error(line, format!("`{}` must be at least {}", key, minimum)));
+    }
+    Ok(value as i32)
+}
+
+/// what a piece of equipment adds to a stat, 0 if not given; may be negative
+fn read_bonus(reader: &mut TableReader, key: &'static str) -> Result<i32, DataError> {
+    let value = reader.integer_or(key, 0)?;
+    let limit = i64::from(i32::MAX);
+    if !(-limit..=limit).contains(&value) {
+        let line = reader.required(key)?.line;
+        return Err(reader.error(
+            line,
+            format!("`{}` must be from {} to {}", key, -limit, limit),
+        ));
+    }
+    Ok(value as i32)
+}
+
+fn read_percent(reader: &mut TableReader, key: &'static str) -> Result<i32, DataError> {
+    let value = reader.integer(key)?;
+    if !(1..=100).contains(&value) {
+        let line = reader.required(key)?.line;
+        return Err(reader.error(
+            line,
+            format!("`{}` must be a percentage from 1 to 100", key),
+        ));
+    }
+    Ok(value as i32)
+}
+
+/// `carries = [[item, chance], ...]`: the items by name, each with the percent chance of having it
+fn read_carries(
+    reader: &mut TableReader,
+    items: &[ItemTemplate],
+) -> Result<Option<Vec<Carried>>, DataError> {
+    let entry = match reader.optional("carries") {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let expected = "an array of [item, chance] pairs";
+
+    let pairs = match &entry.value {
+        Value::Array(pairs) => pairs,
+        _ => return Err(reader.expected(entry, expected)),
+    };
+
+    let mut carries = vec![];
+    for pair in pairs {
+        let (name, chance) = match pair {
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::String(name), Value::Integer(chance)] => (name, *chance),
+                _ => return Err(reader.expected(entry, expected)),
+            },
+            _ => return Err(reader.expected(entry, expected)),
+        };
+
+        let item = match items.iter().position(|item| item.name == *name) {
+            Some(item) => item,
+            None => {
+                return Err(reader.error(
+                    entry.line,
+                    format!("`carries` names '{}', which isn't an [[item]]", name),
+                ))
+            }
+        };
+        if !(1..=100).contains(&chance) {
+            return Err(reader.error(
+                entry.line,
+                "`carries` chances must be percentages from 1 to 100",
+            ));
+        }
+        carries.push(Carried {
+            item,
+            chance: chance as i32,
+        });
+    }
+
+    Ok(Some(carries))
+}
+
+/// `spawn = [[level, weight], ...]`: the weight applies from that dungeon level on
+fn read_spawn(reader: &mut TableReader) -> Result<Vec<Transition>, DataError> {
+    let entry = reader.required("spawn")?;
+    let expected = "an array of [level, weight] pairs";
+
+    let pairs = match &entry.value {
+        Value::Array(pairs) if !pairs.is_empty() => pairs,
+        _ => return Err(reader.expected(entry, expected)),
+    };
+
+    let mut spawn: Vec<Transition> = vec![];
+    for pair in pairs {
+        let transition = match pair {
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::Integer(level), Value::Integer(value)] if *level >= 1 && *value >= 0 => {
+                    Transition {
+                        level: *level as u32,
+                        value: *value as u32,
+                    }
+                }
+                _ => {
+                    return Err(reader.error(
+                        entry.line,
+                        "`spawn` levels must be 1 or more and weights 0 or more",
+                    ))
+                }
+            },
+            _ => return Err(reader.expected(entry, expected)),
+        };
+
+        if spawn
+            .last()
+            .is_some_and(|last| last.level >= transition.level)
+        {
+            return Err(reader.error(entry.line, "`spawn` levels must be in increasing order"));
+        }
+        spawn.push(transition);
+    }
+
+    Ok(spawn)
+}
+
+/// make sure that on every dungeon level at least one template of the kind can spawn
+fn check_spawn_weights<'a>(
+    document: &Document,
+    kind: &str,
+    spawns: impl Iterator<Item = &'a Vec<Transition>> + Clone,
+) -> Result<(), DataError> {
+    // weights only change at the levels listed, so those are the only ones to check
+    let mut levels: Vec<u32> = spawns.clone().flatten().map(|t| t.level).collect();
+    levels.push(1);
+
+    for level in levels {
+        let total: u32 = spawns
+            .clone()
+            .map(|spawn| from_dungeon_level(spawn, level))
+            .sum();
+        if total == 0 {
+            return Err(document.error(
+                0,
+                format!("no {} can spawn on dungeon level {}", kind, level),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, CONFIG_PATH};
+
+    const ORC: &str = r#"
+[[monster]]
+name = "orc"
+glyph = "o"
+color = [63, 127, 63]
+hp = 10
+defense = 0
+power = 3
+xp = 35
+ai = "basic"
+spawn = [[1, 80]]
+"#;
+
+    const SWORD: &str = r#"
+[[item]]
+name = "sword"
+glyph = "/"
+color = [0, 191, 255]
+effect = "equip"
+slot = "main hand"
+power_bonus = 3
+spawn = [[1, 5]]
+"#;
+
+    fn read(text: &str) -> Result<Templates, DataError> {
+        Templates::read(&Document::parse("templates.toml", text)?)
+    }
+
+    /// the line and message of the error the templates give
+    fn error(text: &str) -> (usize, String) {
+        let error = read(text).unwrap_err();
+        (error.line, error.message)
+    }
+
+    #[test]
+    fn the_shipped_templates_load() {
+        let config = Config::load(CONFIG_PATH, true, &[]).unwrap();
+        let templates = Templates::load(&config.templates_path).unwrap();
+        assert!(!templates.monsters.is_empty() && !templates.items.is_empty());
+
+        let templates = read(&format!("{}{}", ORC, SWORD)).unwrap();
+        let sword = &templates.items[0];
+        assert_eq!(sword.equipment.unwrap().power_bonus, 3);
+        assert!(!sword.stackable);
+    }
+
+    #[test]
+    fn names_are_unique_across_monsters_and_items() {
+        let text = format!("{}{}", ORC, SWORD.replace("sword", "orc"));
+        assert_eq!(error(&text), (0, "'orc' is defined more than once".into()));
+    }
+
+    #[test]
+    fn an_unknown_slot_is_refused() {
+        let text = format!("{}{}", ORC, SWORD.replace("main hand", "tail"));
+        let (line, message) = error(&text);
+        assert_eq!(line, 18);
+        assert!(message.starts_with("item 'sword': unknown slot 'tail'"));
+    }
+
+    #[test]
+    fn bonuses_out_of_range_are_refused() {
+        let text = format!(
+            "{}{}",
+            ORC,
+            SWORD.replace("power_bonus = 3", "power_bonus = 4294967298")
+        );
+        assert_eq!(
+            error(&text),
+            (
+                19,
+                "item 'sword': `power_bonus` must be from -2147483647 to 2147483647".into()
+            )
+        );
+
+        let text = format!(
+            "{}{}",
+            ORC,
+            SWORD.replace("power_bonus = 3", "defense_bonus = -1")
+        );
+        assert_eq!(
+            read(&text).unwrap().items[0]
+                .equipment
+                .unwrap()
+                .defense_bonus,
+            -1
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_named() {
+        let text = format!("{}{}", ORC.replace("hp = 10\n", ""), SWORD);
+        assert_eq!(error(&text), (2, "monster 'orc': `hp` is missing".into()));
+        let text = format!("{}{}", ORC, SWORD.replace("slot = \"main hand\"\n", ""));
+        assert_eq!(error(&text), (13, "item 'sword': `slot` is missing".into()));
+    }
+}
@@ -0,0 +1,574 @@
+//! A reader for the data and config files. They're named `.toml` so editors highlight them,
+//! but the format is our own, a strict subset of TOML: comments, `key = value` pairs, `[table]`
+//! and `[[array of tables]]` headers, and values that are integers, floats, booleans,
+//! double-quoted strings or (nested) arrays of those, each on a single line. Single-quoted
+//! and multi-line strings, multi-line arrays, inline tables, dotted keys and dates are
+//! refused with an error rather than read. Every value remembers the line it came from so
+//! errors can point at the offending entry.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+/// A problem with a data file, located by file and line
+#[derive(Debug)]
+pub struct DataError {
+    pub path: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.path, self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Error for DataError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// how the type of the value is called in error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a number",
+            Value::Boolean(_) => "a boolean",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+        }
+    }
+
+    /// parse a lone value, eg. one given on the command line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        if chars.any(|c| !c.is_whitespace()) {
+            return Err(format!("unexpected text after the value `{}`", text));
+        }
+        Ok(value)
+    }
+}
+
+/// One `key = value` line
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// The entries under a `[name]` or `[[name]]` header; the entries before any header form a
+/// table with an empty name
+#[derive(Clone, Debug)]
+pub struct Table {
+    pub name: String,
+    /// whether it's one of the `[[name]]` tables, which can repeat
+    pub array: bool,
+    pub line: usize,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Document {
+    pub path: String,
+    pub tables: Vec<Table>,
+}
+
+impl Document {
+    pub fn load(path: &str) -> Result<Self, DataError> {
+        let text = fs::read_to_string(path).map_err(|error| DataError {
+            path: path.into(),
+            line: 0,
+            message: error.to_string(),
+        })?;
+        Document::parse(path, &text)
+    }
+
+    pub fn parse(path: &str, text: &str) -> Result<Self, DataError> {
+        let mut tables = vec![Table {
+            name: String::new(),
+            array: false,
+            line: 0,
+            entries: vec![],
+        }];
+
+        for (index, raw_line) in text.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| DataError {
+                path: path.into(),
+                line,
+                message,
+            };
+
+            let content = strip_comment(raw_line).trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = content.strip_prefix('[') {
+                let (array, name) = match header.strip_prefix('[') {
+                    Some(header) => (true, header.strip_suffix("]]")),
+                    None => (false, header.strip_suffix(']')),
+                };
+                let name = match name.map(str::trim) {
+                    Some(name) if is_bare_key(name) => name,
+                    _ => return Err(error(format!("invalid table header `{}`", content))),
+                };
+
+                // a `[name]` only once, and never as well as a `[[name]]`
+                if let Some(other) = tables.iter().find(|table| table.name == name) {
+                    if !array || !other.array {
+                        return Err(error(format!(
+                            "`{}` was already defined on line {}",
+                            content, other.line
+                        )));
+                    }
+                }
+                tables.push(Table {
+                    name: name.into(),
+                    array,
+                    line,
+                    entries: vec![],
+                });
+                continue;
+            }
+
+            let (key, value) = match content.find('=') {
+                Some(position) => (content[..position].trim(), content[position + 1..].trim()),
+                None => return Err(error(format!("expected `key = value`, got `{}`", content))),
+            };
+            if !is_bare_key(key) {
+                return Err(error(format!("invalid key `{}`", key)));
+            }
+
+            let mut chars = value.chars().peekable();
+            let value = parse_value(&mut chars).map_err(error)?;
+            if chars.any(|c| !c.is_whitespace()) {
+                return Err(error(format!(
+                    "unexpected text after the value of `{}`",
+                    key
+                )));
+            }
+
+            let table = tables.last_mut().unwrap();
+            if table.entries.iter().any(|entry| entry.key == key) {
+                return Err(error(format!("`{}` is defined twice", key)));
+            }
+            table.entries.push(Entry {
+                key: key.into(),
+                value,
+                line,
+            });
+        }
+
+        Ok(Document {
+            path: path.into(),
+            tables,
+        })
+    }
+
+    /// the `[[name]]` tables, in file order
+    pub fn tables_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Table> {
+        self.tables
+            .iter()
+            .filter(move |table| table.array && table.name == name)
+    }
+
+    /// the entries before the first header
+    pub fn root(&self) -> &Table {
+        &self.tables[0]
+    }
+
+    pub fn error<T: Into<String>>(&self, line: usize, message: T) -> DataError {
+        DataError {
+            path: self.path.clone(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Table {
+    /// the header as it's written in the file
+    pub fn header(&self) -> String {
+        if self.array {
+            format!("[[{}]]", self.name)
+        } else {
+            format!("[{}]", self.name)
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+}
+
+/// Reads typed values out of a table, naming the table in every error, and complains about
+/// keys that were never read (most likely typos) once `finish` is called.
+pub struct TableReader<'a> {
+    document: &'a Document,
+    table: &'a Table,
+    context: String,
+    read: Vec<&'a str>,
+}
+
+impl<'a> TableReader<'a> {
+    /// `context` describes the table in errors, eg. "monster 'orc'"
+    pub fn new(document: &'a Document, table: &'a Table, context: String) -> Self {
+        TableReader {
+            document,
+            table,
+            context,
+            read: vec![],
+        }
+    }
+
+    pub fn error<T: fmt::Display>(&self, line: usize, message: T) -> DataError {
+        let line = if line > 0 { line } else { self.table.line };
+        self.document
+            .error(line, format!("{}: {}", self.context, message))
+    }
+
+    pub fn optional(&mut self, key: &'a str) -> Option<&'a Entry> {
+        self.read.push(key);
+        self.table.get(key)
+    }
+
+    pub fn required(&mut self, key: &'a str) -> Result<&'a Entry, DataError> {
+        self.optional(key)
+            .ok_or_else(|| self.error(0, format!("`{}` is missing", key)))
+    }
+
+    /// an error saying the entry's value should have been something else
+    pub fn expected(&self, entry: &Entry, expected: &str) -> DataError {
+        self.error(
+            entry.line,
+            format!(
+                "`{}` must be {}, not {}",
+                entry.key,
+                expected,
+                entry.value.kind()
+            ),
+        )
+    }
+
+    pub fn string(&mut self, key: &'a str) -> Result<String, DataError> {
+        let entry = self.required(key)?;
+        match &entry.value {
+            Value::String(text) => Ok(text.clone()),
+            _ => Err(self.expected(entry, "a string")),
+        }
+    }
+
+    pub fn integer(&mut self, key: &'a str) -> Result<i64, DataError> {
+        let entry = self.required(key)?;
+        match entry.value {
+            Value::Integer(integer) => Ok(integer),
+            _ => Err(self.expected(entry, "an integer")),
+        }
+    }
+
+    pub fn integer_or(&mut self, key: &'a str, default: i64) -> Result<i64, DataError> {
+        match self.table.get(key) {
+            Some(_) => self.integer(key),
+            None => Ok(default),
+        }
+    }
+
+    pub fn string_or(&mut self, key: &'a str, default: &str) -> Result<String, DataError> {
+        match self.table.get(key) {
+            Some(_) => self.string(key),
+            None => Ok(default.into()),
+        }
+    }
+
+    pub fn boolean_or(&mut self, key: &'a str, default: bool) -> Result<bool, DataError> {
+        match self.optional(key) {
+            Some(Entry {
+                value: Value::Boolean(boolean),
+                ..
+            }) => Ok(*boolean),
+            Some(entry) => Err(self.expected(entry, "a boolean")),
+            None => Ok(default),
+        }
+    }
+
+    /// fail on any key that was never asked for
+    pub fn finish(self) -> Result<(), DataError> {
+        match self
+            .table
+            .entries
+            .iter()
+            .find(|entry| !self.read.contains(&entry.key.as_str()))
+        {
+            Some(entry) => Err(self.error(entry.line, format!("unknown key `{}`", entry.key))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    // a `#` starts a comment unless it's inside a string
+    let mut in_string = false;
+    let mut chars = line.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                // skip whatever is escaped, it could be a quote
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Value, String> {
+    skip_whitespace(chars);
+
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(Value::String(text)),
+                    Some('\\') => match chars.next() {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some(c @ '"') | Some(c @ '\\') => text.push(c),
+                        Some(c) => return Err(format!("unknown escape `\\{}`", c)),
+                        None => return Err("unfinished string".into()),
+                    },
+                    Some(c) => text.push(c),
+                    None => return Err("unfinished string, strings end on their line".into()),
+                }
+            }
+        }
+        Some('\'') => Err("only double-quoted strings are supported".into()),
+        Some('{') => Err("inline tables are not supported".into()),
+        Some('[') => {
+            chars.next();
+            let mut values = vec![];
+            loop {
+                skip_whitespace(chars);
+                match chars.peek() {
+                    Some(']') => {
+                        chars.next();
+                        return Ok(Value::Array(values));
+                    }
+                    None => return Err("unfinished array, arrays end on their line".into()),
+                    _ => {}
+                }
+
+                values.push(parse_value(chars)?);
+
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(values)),
+                    None => return Err("unfinished array, arrays end on their line".into()),
+                    _ => return Err("expected `,` or `]` in array".into()),
+                }
+            }
+        }
+        Some(_) => {
+            // a bare word: boolean or number
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == ']' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+
+            match word.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                _ => {
+                    let number = word.replace('_', "");
+                    if let Ok(integer) = number.parse() {
+                        Ok(Value::Integer(integer))
+                    } else if let Ok(float) = number.parse() {
+                        Ok(Value::Float(float))
+                    } else {
+                        Err(format!("invalid value `{}`", word))
+                    }
+                }
+            }
+        }
+        None => Err("missing value".into()),
+    }
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Document, DataError> {
+        Document::parse("test.toml", text)
+    }
+
+    /// the line and message of the error the text gives
+    fn error(text: &str) -> (usize, String) {
+        let error = parse(text).unwrap_err();
+        (error.line, error.message)
+    }
+
+    #[test]
+    fn values_and_tables() {
+        let document = parse(
+            "top = 1 # a comment\n\
+             \n\
+             [map]\n\
+             width = 80\n\
+             ratio = 0.5\n\
+             big = 1_000\n\
+             walls = false\n\
+             name = \"a \\\"quoted\\\" # not a comment\"\n\
+             pairs = [[\"orc\", 20], [], [-3]]\n\
+             [[item]]\n\
+             name = \"sword\"\n\
+             [[item]]\n\
+             name = \"helmet\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(document.root().get("top").unwrap().value, Value::Integer(1));
+        let map = &document.tables[1];
+        assert_eq!((map.name.as_str(), map.array, map.line), ("map", false, 3));
+        let value = |key| map.get(key).unwrap().value.clone();
+        assert_eq!(value("width"), Value::Integer(80));
+        assert_eq!(value("ratio"), Value::Float(0.5));
+        assert_eq!(value("big"), Value::Integer(1000));
+        assert_eq!(value("walls"), Value::Boolean(false));
+        assert_eq!(
+            value("name"),
+            Value::String("a \"quoted\" # not a comment".into())
+        );
+        assert_eq!(
+            value("pairs"),
+            Value::Array(vec![
+                Value::Array(vec![Value::String("orc".into()), Value::Integer(20)]),
+                Value::Array(vec![]),
+                Value::Array(vec![Value::Integer(-3)]),
+            ])
+        );
+        assert_eq!(map.get("pairs").unwrap().line, 9);
+
+        let items: Vec<_> = document.tables_named("item").collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].header(), "[[item]]");
+        assert_eq!(items[1].line, 12);
+        assert_eq!(document.tables_named("map").count(), 0);
+    }
+
+    #[test]
+    fn headers_are_not_mixed_up() {
+        assert_eq!(
+            error("[map]\n[fov]\n[map]\n"),
+            (3, "`[map]` was already defined on line 1".into())
+        );
+        assert_eq!(
+            error("[[item]]\n[item]\n"),
+            (2, "`[item]` was already defined on line 1".into())
+        );
+        assert_eq!(
+            error("[item]\n[[item]]\n"),
+            (2, "`[[item]]` was already defined on line 1".into())
+        );
+        assert_eq!(
+            error("[[item]\n"),
+            (1, "invalid table header `[[item]`".into())
+        );
+        assert_eq!(error("[a.b]\n"), (1, "invalid table header `[a.b]`".into()));
+    }
+
+    #[test]
+    fn what_toml_allows_but_we_do_not() {
+        assert_eq!(
+            error("a = [\n  1,\n]\n"),
+            (1, "unfinished array, arrays end on their line".into())
+        );
+        assert_eq!(
+            error("\na = 'text'\n"),
+            (2, "only double-quoted strings are supported".into())
+        );
+        assert_eq!(
+            error("a = { b = 1 }\n"),
+            (1, "inline tables are not supported".into())
+        );
+        assert_eq!(error("a.b = 1\n"), (1, "invalid key `a.b`".into()));
+    }
+
+    #[test]
+    fn mistakes_point_at_their_line() {
+        assert_eq!(error("a = 1\na = 2\n"), (2, "`a` is defined twice".into()));
+        assert_eq!(
+            error("a = 1\n\nb\n"),
+            (3, "expected `key = value`, got `b`".into())
+        );
+        assert_eq!(
+            error("a = \"open\n"),
+            (1, "unfinished string, strings end on their line".into())
+        );
+        assert_eq!(error("a = \"\\q\"\n"), (1, "unknown escape `\\q`".into()));
+        assert_eq!(
+            error("a = 1 2\n"),
+            (1, "unexpected text after the value of `a`".into())
+        );
+        assert_eq!(error("a = yes\n"), (1, "invalid value `yes`".into()));
+        assert_eq!(error("a =\n"), (1, "missing value".into()));
+        assert_eq!(
+            error("a = [1 2]\n"),
+            (1, "expected `,` or `]` in array".into())
+        );
+    }
+
+    #[test]
+    fn the_reader_names_the_table_and_unread_keys() {
+        let document = parse("[map]\nwidth = \"wide\"\nheigth = 3\n").unwrap();
+        let table = &document.tables[1];
+
+        let mut reader = TableReader::new(&document, table, table.header());
+        let error = reader.integer("width").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test.toml:2: [map]: `width` must be an integer, not a string"
+        );
+        assert_eq!(reader.integer_or("height", 43).unwrap(), 43);
+        assert_eq!(
+            reader.finish().unwrap_err().to_string(),
+            "test.toml:3: [map]: unknown key `heigth`"
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! The entity store. An entity is nothing but an id; what it is and does comes from the
+//! components attached to it, one table per kind of component. Systems iterate the tables
+//! they care about, and because ids carry a generation, an entity removed in the middle of a
+//! turn simply stops showing up instead of being confused with whatever reuses its slot.
+
+use crate::domain::{Ai, Equipment, Fighter, Inventory, Item, Position, Renderable, Stack};
+use crate::pathfinding::Path;
+use crate::scheduler::Actor;
+
+/// A handle to an entity. The generation tells apart the entities that reuse the same slot,
+/// so a handle kept after its entity was despawned never reaches the newcomer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Entity {
+    pub index: u32,
+    pub generation: u32,
+}
+
+/// One kind of component for every entity that has it, indexed by entity slot
+#[derive(Clone, Debug)]
+pub struct Components<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Components<T> {
+    pub fn new() -> Self {
+        Components { slots: vec![] }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index as usize) {
+            Some(Some((generation, component))) if *generation == entity.generation => {
+                Some(component)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index as usize) {
+            Some(Some((generation, component))) if *generation == entity.generation => {
+                Some(component)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// attach the component, replacing the one the entity already had
+    pub fn insert(&mut self, entity: Entity, component: T) {
+        let index = entity.index as usize;
+        if self.slots.len() <= index {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.slots[index] = Some((entity.generation, component));
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        if !self.contains(entity) {
+            return None;
+        }
+        self.slots[entity.index as usize]
+            .take()
+            .map(|(_, component)| component)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref().map(|(generation, component)| {
+                let entity = Entity {
+                    index: index as u32,
+                    generation: *generation,
+                };
+                (entity, component)
+            })
+        })
+    }
+
+    /// the entities that have this component right now; a copy, so the table can be changed
+    /// while going through them
+    pub fn entities(&self) -> Vec<Entity> {
+        self.iter().map(|(entity, _)| entity).collect()
+    }
+}
+
+/// Every entity of the current level, plus the ones carried around in inventories
+#[derive(Clone, Debug)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    /// the player, who is spawned first and never despawned
+    pub player: Entity,
+
+    pub names: Components<String>,
+    pub positions: Components<Position>,
+    pub renderables: Components<Renderable>,
+    /// entities nothing else can walk through
+    pub blocks: Components<()>,
+    pub fighters: Components<Fighter>,
+    pub ais: Components<Ai>,
+    pub items: Components<Item>,
+    pub equipment: Components<Equipment>,
+    /// how many there are of the items that stack
+    pub stacks: Components<Stack>,
+    pub inventories: Components<Inventory>,
+    /// the entities that take turns
+    pub actors: Components<Actor>,
+    /// routes the monsters are following; only a cache, so it isn't saved
+    pub paths: Components<Path>,
+}
+
+impl World {
+    /// an empty world; `player` is only meaningful once the player has been spawned
+    pub fn new() -> Self {
+        World {
+            generations: vec![],
+            alive: vec![],
+            player: Entity {
+                index: 0,
+                generation: 0,
+            },
+            names: Components::new(),
+            positions: Components::new(),
+            renderables: Components::new(),
+            blocks: Components::new(),
+            fighters: Components::new(),
+            ais: Components::new(),
+            items: Components::new(),
+            equipment: Components::new(),
+            stacks: Components::new(),
+            inventories: Components::new(),
+            actors: Components::new(),
+            paths: Components::new(),
+        }
+    }
+
+    /// a new entity without any components, reusing the slot of a despawned one if possible
+    pub fn spawn(&mut self) -> Entity {
+        match self.alive.iter().position(|alive| !alive) {
+            Some(index) => {
+                self.alive[index] = true;
+                Entity {
+                    index: index as u32,
+                    generation: self.generations[index],
+                }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// remove the entity, all of its components and everything it carries; the handles
+    /// become stale
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.contains(entity) {
+            return;
+        }
+        if let Some(inventory) = self.inventories.remove(entity) {
+            for item in inventory.items {
+                self.despawn(item);
+            }
+        }
+
+        self.names.remove(entity);
+        self.positions.remove(entity);
+        self.renderables.remove(entity);
+        self.blocks.remove(entity);
+        self.fighters.remove(entity);
+        self.ais.remove(entity);
+        self.items.remove(entity);
+        self.equipment.remove(entity);
+        self.stacks.remove(entity);
+        self.actors.remove(entity);
+        self.paths.remove(entity);
+
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] += 1;
+    }
+
+    /// whether the handle still refers to a live entity
+    pub fn contains(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+    }
+
+    /// every live entity, in slot order
+    pub fn entities(&self) -> Vec<Entity> {
+        (0..self.alive.len())
+            .filter(|&index| self.alive[index])
+            .map(|index| Entity {
+                index: index as u32,
+                generation: self.generations[index],
+            })
+            .collect()
+    }
+
+    /// bring back a live entity exactly as it was saved, handle included
+    pub fn restore(&mut self, entity: Entity) {
+        let index = entity.index as usize;
+        if self.alive.len() <= index {
+            self.alive.resize(index + 1, false);
+            self.generations.resize(index + 1, 0);
+        }
+        self.alive[index] = true;
+        self.generations[index] = entity.generation;
+    }
+
+    pub fn name(&self, entity: Entity) -> &str {
+        self.names.get(entity).map_or("", |name| name.as_str())
+    }
+
+    pub fn pos(&self, entity: Entity) -> Option<(i32, i32)> {
+        self.positions.get(entity).map(|p| (p.x, p.y))
+    }
+
+    pub fn set_pos(&mut self, entity: Entity, x: i32, y: i32) {
+        self.positions.insert(entity, Position { x, y });
+    }
+
+    /// take the item out of the owner's inventory, leaving it nowhere until it's put somewhere
+    pub fn take_from_inventory(&mut self, owner: Entity, item: Entity) {
+        if let Some(inventory) = self.inventories.get_mut(owner) {
+            inventory.items.retain(|&carried| carried != item);
+        }
+    }
+
+    /// the items the entity carries, empty if it has no inventory
+    pub fn inventory(&self, entity: Entity) -> &[Entity] {
+        self.inventories
+            .get(entity)
+            .map_or(&[], |inventory| inventory.items.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an item carried by `owner`, with a name so it can be told apart
+    fn carry(owner: Entity, name: &str, world: &mut World) -> Entity {
+        let item = world.spawn();
+        world.names.insert(item, name.into());
+        world.items.insert(item, Item::Heal);
+        world.inventories.get_mut(owner).unwrap().items.push(item);
+        item
+    }
+
+    #[test]
+    fn a_stale_handle_reaches_nothing_once_its_slot_is_reused() {
+        let mut world = World::new();
+        let orc = world.spawn();
+        world.names.insert(orc, "orc".into());
+        world.set_pos(orc, 3, 4);
+        world.blocks.insert(orc, ());
+        world.actors.insert(orc, Actor::new(100));
+
+        world.despawn(orc);
+        let troll = world.spawn();
+        world.names.insert(troll, "troll".into());
+        world.set_pos(troll, 5, 6);
+        world.blocks.insert(troll, ());
+        world.actors.insert(troll, Actor::new(100));
+
+        assert_eq!(troll.index, orc.index);
+        assert_ne!(troll.generation, orc.generation);
+        assert!(!world.contains(orc));
+        assert_eq!(world.names.get(orc), None);
+        assert_eq!(world.pos(orc), None);
+        assert!(!world.blocks.contains(orc));
+        assert!(world.actors.get(orc).is_none());
+        assert!(world.names.remove(orc).is_none());
+        assert_eq!(world.name(troll), "troll");
+        assert_eq!(world.pos(troll), Some((5, 6)));
+        assert_eq!(world.entities(), vec![troll]);
+    }
+
+    #[test]
+    fn despawning_an_owner_takes_what_it_carries_along() {
+        let mut world = World::new();
+        let orc = world.spawn();
+        world.inventories.insert(orc, Inventory::default());
+        let potion = carry(orc, "potion", &mut world);
+        // a bag in the bag, which is carried too
+        let bag = carry(orc, "bag", &mut world);
+        world.inventories.insert(bag, Inventory::default());
+        let scroll = carry(bag, "scroll", &mut world);
+        let bystander = world.spawn();
+
+        world.despawn(orc);
+        for entity in [orc, potion, bag, scroll] {
+            assert!(!world.contains(entity));
+            assert!(!world.items.contains(entity) && !world.names.contains(entity));
+        }
+        assert_eq!(world.entities(), vec![bystander]);
+        // despawning again changes nothing
+        world.despawn(orc);
+        assert_eq!(world.entities(), vec![bystander]);
+    }
+
+    #[test]
+    fn restored_entities_keep_their_handle_and_their_slot() {
+        let saved = Entity {
+            index: 2,
+            generation: 5,
+        };
+        let mut world = World::new();
+        world.restore(saved);
+        world.names.insert(saved, "orc".into());
+        assert!(world.contains(saved));
+        assert_eq!(world.name(saved), "orc");
+        assert_eq!(world.entities(), vec![saved]);
+
+        // the free slots before it are handed out, then new ones, never the restored one
+        let spawned: Vec<_> = (0..3).map(|_| world.spawn()).collect();
+        let indexes: Vec<_> = spawned.iter().map(|entity| entity.index).collect();
+        assert_eq!(indexes, vec![0, 1, 3]);
+        assert!(spawned.iter().all(|&entity| world.contains(entity)));
+        assert_eq!(world.name(saved), "orc");
+    }
+}